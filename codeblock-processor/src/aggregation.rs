@@ -0,0 +1,9 @@
+use pulldown_cmark::Event;
+
+use crate::codeblock::Codeblock;
+
+#[derive(Debug)]
+pub enum Aggregation<'a> {
+    Event(Event<'a>),
+    Codeblock(Codeblock),
+}
@@ -0,0 +1,126 @@
+//! AsciiDoc-style callouts: a comment like `// <1>` at the end of a line marks it with a numbered
+//! badge, explained by the first item of the ordered list following the code block.
+
+use std::collections::BTreeSet;
+
+use anyhow::bail;
+use once_cell::sync::Lazy;
+use pulldown_cmark::{Event, Tag, TagEnd};
+use regex::Regex;
+
+/// A trailing `//`, `#` or `--` comment of only callout numbers, such as `// <1> <2>`.
+static CALLOUT: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\s*(?://|#|--)\s*((?:<\d+>\s*)+)$").expect("invalid regex"));
+
+/// Split a line into its code without the callout comment and its callout numbers.
+pub fn split(line: &str) -> (&str, Vec<usize>) {
+    let Some(captures) = CALLOUT.captures(line) else {
+        return (line, Vec::new());
+    };
+    let numbers = captures[1]
+        .split(['<', '>'])
+        .filter_map(|number| number.trim().parse().ok())
+        .collect();
+    let start = captures.get(0).expect("Match must exist").start();
+    (&line[..start], numbers)
+}
+
+/// The code with the callout comments removed, as sent to the playground and other targets.
+pub fn strip(code: &str) -> String {
+    code.split_inclusive('\n')
+        .map(|line| match line.strip_suffix('\n') {
+            Some(content) => format!("{}\n", split(content).0),
+            None => split(line).0.to_string(),
+        })
+        .collect()
+}
+
+/// All callout numbers in the code.
+pub fn numbers(code: &str) -> BTreeSet<usize> {
+    code.lines().flat_map(|line| split(line).1).collect()
+}
+
+/// Fail if a callout of the code has no list item explaining it.
+pub fn check(code: &str, items: &[String]) -> anyhow::Result<()> {
+    if let Some(number) = numbers(code)
+        .into_iter()
+        .find(|&number| number == 0 || number > items.len())
+    {
+        match items.len() {
+            0 => bail!("Callout <{number}> has no list item, add a numbered list after the code block"),
+            count => bail!("Callout <{number}> has no list item, the list after the code block has {count} items"),
+        }
+    }
+    Ok(())
+}
+
+/// The text of the items of the ordered list at the start of `events`, which follow a code block.
+pub fn list_items<'a>(events: impl IntoIterator<Item = &'a Event<'a>>) -> Vec<String> {
+    let mut events = events.into_iter();
+    if !matches!(events.next(), Some(Event::Start(Tag::List(Some(_))))) {
+        return Vec::new();
+    }
+    let mut items = Vec::new();
+    // Depth of nested lists and items, the list itself is at depth 1.
+    let mut depth = 1;
+    for event in events {
+        match event {
+            Event::Start(Tag::Item) if depth == 1 => {
+                items.push(String::new());
+                depth += 1;
+            }
+            Event::Start(Tag::List(_) | Tag::Item) => depth += 1,
+            Event::End(TagEnd::List(_)) if depth == 1 => break,
+            Event::End(TagEnd::List(_) | TagEnd::Item) => depth -= 1,
+            Event::Text(text) | Event::Code(text) if depth == 2 => {
+                if let Some(item) = items.last_mut() {
+                    item.push_str(text);
+                }
+            }
+            Event::SoftBreak | Event::HardBreak if depth == 2 => {
+                if let Some(item) = items.last_mut() {
+                    item.push(' ');
+                }
+            }
+            _ => {}
+        }
+    }
+    items
+}
+
+#[cfg(test)]
+mod test {
+    use pulldown_cmark::Parser;
+
+    use super::*;
+
+    #[test]
+    fn splits_and_strips_callouts() {
+        assert_eq!(split("let x = 1; // <1>"), ("let x = 1;", vec![1]));
+        assert_eq!(split("x = 1  # <2> <3>"), ("x = 1", vec![2, 3]));
+        assert_eq!(
+            split("// <1> is a callout"),
+            ("// <1> is a callout", vec![])
+        );
+        assert_eq!(
+            strip("let a = 1; // <1>\nlet b = a; // <2>\n"),
+            "let a = 1;\nlet b = a;\n"
+        );
+    }
+
+    #[test]
+    fn attaches_list_items() {
+        let events =
+            Parser::new("1. The *first* `a`\n   - nested\n2. The second\n").collect::<Vec<_>>();
+        let items = list_items(&events);
+        assert_eq!(items, ["The first a", "The second"]);
+
+        let code = "a // <1>\nb // <3>\n";
+        assert!(check("a // <2>\n", &items).is_ok());
+        assert_eq!(
+            check(code, &items).unwrap_err().to_string(),
+            "Callout <3> has no list item, the list after the code block has 2 items"
+        );
+        assert!(list_items(&Parser::new("- bullet\n").collect::<Vec<_>>()).is_empty());
+    }
+}
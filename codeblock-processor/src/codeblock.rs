@@ -0,0 +1,248 @@
+use std::ops::Range;
+
+use pulldown_cmark::{CodeBlockKind, Event, Tag, TagEnd};
+use snippet_extractor::fence::{FenceError, FenceInfo};
+
+use crate::pass::{Button, ExtraBlock};
+use crate::render;
+use crate::theme::Theme;
+
+/// A code block with its content, as one complete datastructure suitable to run passes on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Codeblock {
+    /// The info string after the opening fence, or `None` for indented code blocks.
+    pub fence: Option<String>,
+    /// The parsed info string. Malformed attributes are skipped.
+    pub info: FenceInfo,
+    /// Syntax errors in the info string.
+    pub fence_errors: Vec<FenceError>,
+    /// The text inside the code block.
+    pub content: String,
+    /// Byte range of the whole code block in the markdown source.
+    pub range: Range<usize>,
+    /// 1-based line of the opening fence.
+    pub line: usize,
+    /// 1-based column of the opening fence.
+    pub column: usize,
+    /// 1-based line in the source file where the content starts, if it was read from a file.
+    pub source_line: Option<usize>,
+    /// Whether a previous pass replaced the code block with HTML, such as a diagram.
+    pub rendered: bool,
+    /// Text of the items of the ordered list directly after the code block, explaining its
+    /// callouts, see [`crate::callout`].
+    pub callouts: Vec<String>,
+    /// Code of all blocks of its `playground-group:`, if the block belongs to one,
+    /// see [`crate::group`].
+    pub group: Option<String>,
+}
+
+impl Codeblock {
+    /// Create a code block, computing its line and column from the markdown source.
+    pub fn new(fence: Option<String>, content: String, range: Range<usize>, source: &str) -> Self {
+        let (line, column) = position(source, range.start);
+        let mut codeblock = Self {
+            fence: None,
+            info: FenceInfo::default(),
+            fence_errors: Vec::new(),
+            content,
+            range,
+            line,
+            column,
+            source_line: None,
+            rendered: false,
+            callouts: Vec::new(),
+            group: None,
+        };
+        codeblock.set_fence(fence);
+        codeblock
+    }
+
+    /// Move the code block to `range` of another markdown source, computing its line and column.
+    pub fn relocate(&mut self, range: Range<usize>, source: &str) {
+        (self.line, self.column) = position(source, range.start);
+        self.range = range;
+    }
+
+    /// The code sent to the playground, compiled and run: the whole playground group, or the block.
+    pub fn program(&self) -> &str {
+        self.group.as_deref().unwrap_or(&self.content)
+    }
+
+    /// Replace the info string, parsing it again.
+    pub fn set_fence(&mut self, fence: Option<String>) {
+        let (info, errors) = fence
+            .as_deref()
+            .map(FenceInfo::parse_lossy)
+            .unwrap_or_default();
+        self.fence = fence;
+        self.info = info;
+        self.fence_errors = errors;
+    }
+}
+
+/// 1-based line and column of a byte offset.
+fn position(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |newline| newline + 1) + 1;
+    (line, column)
+}
+
+/// A code block after running all passes on it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessedCodeblock {
+    pub codeblock: Codeblock,
+    pub buttons: Vec<Button>,
+    /// HTML to insert before the code block.
+    pub before: Vec<String>,
+    /// HTML to insert after the code block.
+    pub after: Vec<String>,
+    /// Code blocks to insert after the code block, before `after`.
+    pub blocks: Vec<ExtraBlock>,
+    /// HTML replacing the markdown code block.
+    pub html: Option<String>,
+}
+
+impl ProcessedCodeblock {
+    /// Keep only the selected buttons, in the order of selection.
+    ///
+    /// The `buttons:` fence attribute selects buttons by name, such as `buttons:playground,copy`,
+    /// or suppresses them with `buttons:none`. Otherwise, `defaults` are selected.
+    /// A name also selects all buttons in its group, so `playground` selects `playground:godbolt`.
+    pub fn select_buttons(&mut self, defaults: &[String]) {
+        let selection = match self.codeblock.info.get("buttons") {
+            Some("none") => Vec::new(),
+            Some(selection) => selection.split(',').map(str::trim).collect(),
+            None => defaults.iter().map(String::as_str).collect(),
+        };
+        let mut buttons = std::mem::take(&mut self.buttons);
+        for name in selection {
+            let group = format!("{name}:");
+            let (selected, rest) = buttons
+                .into_iter()
+                .partition(|button| button.name == name || button.name.starts_with(&group));
+            self.buttons.extend::<Vec<_>>(selected);
+            buttons = rest;
+        }
+    }
+
+    /// Render the code block, wrapped with its buttons if `buttons` is set.
+    pub fn into_events<'a>(self, theme: &Theme, buttons: bool) -> Vec<Event<'a>> {
+        let html = |html: String| render::html_block([Event::Html(html.into())]);
+        let mut events = Vec::new();
+        events.extend(self.before.into_iter().flat_map(html));
+        if buttons {
+            events.extend(render::html_block([render::wrapper(
+                &theme.wrapper_start,
+                &self.codeblock,
+            )]));
+        }
+        let wrapper_end = render::wrapper(&theme.wrapper_end, &self.codeblock);
+        let button_events = if buttons && !self.buttons.is_empty() {
+            render::html_block(render::make_buttons(theme, &self.buttons, &self.codeblock))
+        } else {
+            Vec::new()
+        };
+        if let Some(rendered) = self.html {
+            events.extend(html(rendered));
+        } else {
+            let kind = match self.codeblock.fence {
+                Some(fence) => CodeBlockKind::Fenced(fence.into()),
+                None => CodeBlockKind::Indented,
+            };
+            events.push(Event::Start(Tag::CodeBlock(kind)));
+            events.push(Event::Text(self.codeblock.content.into()));
+            events.push(Event::End(TagEnd::CodeBlock));
+        }
+        events.push(Event::Text("\n".into()));
+        if buttons {
+            events.extend(button_events);
+            events.push(Event::Text("\n".into()));
+            events.extend(render::html_block([wrapper_end]));
+        }
+        for block in self.blocks {
+            events.push(Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(
+                block.fence.into(),
+            ))));
+            events.push(Event::Text(block.content.into()));
+            events.push(Event::End(TagEnd::CodeBlock));
+            events.push(Event::Text("\n".into()));
+        }
+        events.extend(self.after.into_iter().flat_map(html));
+        events
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::pass::ButtonAction;
+
+    use super::*;
+
+    #[test]
+    fn computes_line_and_column() {
+        let source = "# Title\n\n- ```rust\n  code\n  ```\n";
+        let start = source.find("```").unwrap();
+        let codeblock = Codeblock::new(
+            Some("rust".into()),
+            "code\n".into(),
+            start..source.len(),
+            source,
+        );
+        assert_eq!(codeblock.line, 3);
+        assert_eq!(codeblock.column, 3);
+    }
+
+    fn processed(fence: &str) -> ProcessedCodeblock {
+        let button = |name: &str| Button {
+            name: name.into(),
+            action: ButtonAction::Copy,
+            label: name.into(),
+        };
+        ProcessedCodeblock {
+            codeblock: Codeblock::new(Some(fence.into()), String::new(), 0..0, ""),
+            buttons: vec![
+                button("editor"),
+                button("copy"),
+                button("playground"),
+                button("playground:godbolt"),
+            ],
+            before: Vec::new(),
+            after: Vec::new(),
+            blocks: Vec::new(),
+            html: None,
+        }
+    }
+
+    fn names(processed: &ProcessedCodeblock) -> Vec<&str> {
+        processed
+            .buttons
+            .iter()
+            .map(|button| button.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn selects_buttons() {
+        let defaults = vec!["playground".to_string(), "editor".to_string()];
+
+        let mut block = processed("rust");
+        block.select_buttons(&defaults);
+        assert_eq!(
+            names(&block),
+            ["playground", "playground:godbolt", "editor"]
+        );
+
+        let mut block = processed("rust buttons:playground:godbolt,copy");
+        block.select_buttons(&defaults);
+        assert_eq!(names(&block), ["playground:godbolt", "copy"]);
+
+        let mut block = processed("rust buttons:copy,editor,unknown");
+        block.select_buttons(&defaults);
+        assert_eq!(names(&block), ["copy", "editor"]);
+
+        let mut block = processed("rust buttons:none");
+        block.select_buttons(&defaults);
+        assert!(block.buttons.is_empty());
+    }
+}
@@ -0,0 +1,126 @@
+use std::ops::Range;
+
+use pulldown_cmark::{CodeBlockKind, Event, Tag, TagEnd};
+
+use crate::{aggregation::Aggregation, codeblock::Codeblock};
+
+#[derive(Debug)]
+struct OpenCodeblock {
+    fence: Option<String>,
+    content: String,
+    range: Range<usize>,
+}
+
+#[derive(Debug)]
+pub struct CodeblockAggregator<'a, I> {
+    source: &'a str,
+    state: Option<OpenCodeblock>,
+    iter: I,
+}
+
+impl<'a, I> CodeblockAggregator<'a, I> {
+    pub fn new(iter: I, source: &'a str) -> Self {
+        Self {
+            source,
+            state: None,
+            iter,
+        }
+    }
+}
+
+/// Walk over an iterator of [`Event`]s with their source ranges.
+/// On encountering the start of a code block, its text, then its end:
+/// pass on an aggregation of the encountered code block.
+/// Otherwise, pass on all items.
+impl<'a, I> Iterator for CodeblockAggregator<'a, I>
+where
+    I: Iterator<Item = (Event<'a>, Range<usize>)>,
+{
+    type Item = Aggregation<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Some((event, range)) = self.iter.next() else {
+                // An unterminated code block still ends with the document.
+                let open = self.state.take()?;
+                return Some(Aggregation::Codeblock(Codeblock::new(
+                    open.fence,
+                    open.content,
+                    open.range,
+                    self.source,
+                )));
+            };
+            match (self.state.take(), event) {
+                (None, Event::Start(Tag::CodeBlock(kind))) => {
+                    let fence = match kind {
+                        CodeBlockKind::Fenced(fence) => Some(fence.to_string()),
+                        CodeBlockKind::Indented => None,
+                    };
+                    self.state = Some(OpenCodeblock {
+                        fence,
+                        content: String::new(),
+                        range,
+                    });
+                }
+                (None, event) => break Some(Aggregation::Event(event)),
+                (Some(mut open), Event::Text(text)) => {
+                    open.content.push_str(&text);
+                    self.state = Some(open);
+                }
+                (Some(open), Event::End(TagEnd::CodeBlock)) => {
+                    break Some(Aggregation::Codeblock(Codeblock::new(
+                        open.fence,
+                        open.content,
+                        open.range,
+                        self.source,
+                    )));
+                }
+                (Some(open), _event) => self.state = Some(open),
+            }
+        }
+    }
+}
+
+pub trait CodeblockTools<'a>: Iterator {
+    fn aggregate_codeblocks(self, source: &'a str) -> CodeblockAggregator<'a, Self>
+    where
+        Self: Sized;
+}
+
+impl<'a, T> CodeblockTools<'a> for T
+where
+    T: Iterator<Item = (Event<'a>, Range<usize>)>,
+{
+    fn aggregate_codeblocks(self, source: &'a str) -> CodeblockAggregator<'a, Self>
+    where
+        Self: Sized,
+    {
+        CodeblockAggregator::new(self, source)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pulldown_cmark::Parser;
+
+    use super::*;
+
+    #[test]
+    fn aggregates_codeblocks() {
+        let md = "# Heading\n\n```rust tag:x\nfn main() {}\n\nlet a = 1;\n```\n\n    indented\n";
+        let codeblocks = Parser::new(md)
+            .into_offset_iter()
+            .aggregate_codeblocks(md)
+            .filter_map(|aggregation| match aggregation {
+                Aggregation::Codeblock(codeblock) => Some(codeblock),
+                Aggregation::Event(_) => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(codeblocks.len(), 2);
+        assert_eq!(codeblocks[0].fence.as_deref(), Some("rust tag:x"));
+        assert_eq!(codeblocks[0].content, "fn main() {}\n\nlet a = 1;\n");
+        assert_eq!(codeblocks[0].line, 3);
+        assert_eq!(codeblocks[1].fence, None);
+        assert_eq!(codeblocks[1].content, "indented\n");
+    }
+}
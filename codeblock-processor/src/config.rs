@@ -0,0 +1,173 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::format::FormatConfig;
+use crate::highlight::HighlightConfig;
+use crate::processor::diagram_renderer::DiagramConfig;
+use crate::processor::line_annotator::LinesConfig;
+use crate::processor::playground_button_inserter::Template;
+use crate::processor::run_output_inserter::RunConfig;
+use crate::processor::snippet_button_inserter::EditorConfig;
+use crate::processor::target_button_inserter::{Godbolt, UrlTarget};
+use crate::theme::ThemeConfig;
+
+/// Name of the configuration file picked up from the working directory.
+pub const CONFIG_FILE: &str = "codeblock.toml";
+
+/// Contents of `codeblock.toml`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Playground templates by name, usable with `playground-wrap:<name>`.
+    pub templates: BTreeMap<String, Template>,
+
+    /// Compiler and flags for `playground:godbolt` by fence language, replacing the builtin ones.
+    pub godbolt: BTreeMap<String, Godbolt>,
+
+    /// Playgrounds given by URL templates, usable with `playground:<name>`.
+    pub targets: BTreeMap<String, UrlTarget>,
+
+    /// Timeout, cache and output style for code blocks marked with `run`.
+    pub run: RunConfig,
+
+    /// Editor and permalink buttons for `marker:` blocks.
+    pub editor: EditorConfig,
+
+    /// HTML templates for wrappers and buttons.
+    pub theme: ThemeConfig,
+
+    /// Settings for `include:` blocks.
+    pub include: IncludeConfig,
+
+    /// Output of `highlight:` and `linenos`.
+    pub lines: LinesConfig,
+
+    /// rustfmt executable, configuration and cache for formatting Rust code.
+    pub fmt: FormatConfig,
+
+    /// Renderers and assets directory for diagram code blocks.
+    pub diagrams: DiagramConfig,
+
+    /// Theme for syntax highlighting at build time.
+    pub highlight: HighlightConfig,
+}
+
+/// Settings for `include:` blocks.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IncludeConfig {
+    /// Directory which included paths are relative to, instead of the markdown document.
+    /// Relative to the configuration file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base: Option<PathBuf>,
+}
+
+impl Config {
+    pub fn from_toml(toml: &str) -> anyhow::Result<Self> {
+        toml::from_str(toml).context("Invalid configuration")
+    }
+
+    /// Load the configuration from `path`, or from `codeblock.toml` if it exists.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(CONFIG_FILE).exists() => Path::new(CONFIG_FILE),
+            None => return Ok(Self::default()),
+        };
+        let toml = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut config = Self::from_toml(&toml)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        if let Some(parent) = path.parent() {
+            for directory in [
+                &mut config.theme.directory,
+                &mut config.include.base,
+                &mut config.fmt.config,
+                &mut config.diagrams.assets,
+                &mut config.highlight.theme_file,
+            ]
+            .into_iter()
+            .flatten()
+            {
+                *directory = parent.join(&*directory);
+            }
+            config.run.cache = parent.join(&config.run.cache);
+            config.fmt.cache = parent.join(&config.fmt.cache);
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_templates() {
+        let config = Config::from_toml(
+            r#"
+[templates.serde]
+pre = """
+use serde::Serialize;
+
+fn main() {
+"""
+post = "}"
+indent = 4
+edition = "2018"
+channel = "nightly"
+"#,
+        )
+        .unwrap();
+        let template = &config.templates["serde"];
+        assert_eq!(template.pre, "use serde::Serialize;\n\nfn main() {\n");
+        assert_eq!(template.post, "}");
+        assert_eq!(template.indent, Some(4));
+        assert_eq!(template.edition.as_deref(), Some("2018"));
+        assert_eq!(template.channel.as_deref(), Some("nightly"));
+    }
+
+    #[test]
+    fn parses_targets() {
+        let config = Config::from_toml(
+            r#"
+[godbolt.rust]
+compiler = "r1750"
+
+[targets.python]
+url = "https://example.com/?code={code}"
+label = "Python"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.godbolt["rust"].compiler, "r1750");
+        assert_eq!(config.godbolt["rust"].flags, "");
+        assert_eq!(config.targets["python"].label.as_deref(), Some("Python"));
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(Config::from_toml("[templates.main]\nprelude = \"\"").is_err());
+        assert!(Config::from_toml("template = 1").is_err());
+    }
+
+    #[test]
+    fn resolves_paths_relative_to_the_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join(CONFIG_FILE);
+        std::fs::write(&path, "[include]\nbase = \"src\"\n").unwrap();
+        let config = Config::load(Some(&path)).unwrap();
+        assert_eq!(config.include.base, Some(directory.path().join("src")));
+        assert_eq!(
+            config.run.cache,
+            directory.path().join(".codeblock-cache/run")
+        );
+        assert_eq!(
+            config.fmt.cache,
+            directory.path().join(".codeblock-cache/fmt")
+        );
+    }
+}
@@ -0,0 +1,420 @@
+//! Formatting Rust code with rustfmt, for playground links and `fmt` code blocks.
+
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
+};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Settings for formatting Rust code.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FormatConfig {
+    /// The rustfmt executable.
+    pub rustfmt: PathBuf,
+
+    /// Configuration file for rustfmt. By default, `rustfmt.toml` or `.rustfmt.toml` is looked up
+    /// from the directory of the document upwards, like rustfmt does for files.
+    /// Relative to the configuration file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<PathBuf>,
+
+    /// Directory for cached results, keyed by the hash of the code, the settings and the rustfmt
+    /// version. Relative to the configuration file.
+    pub cache: PathBuf,
+
+    /// Format with a builtin formatter when rustfmt is not installed.
+    pub fallback: bool,
+}
+
+impl Default for FormatConfig {
+    fn default() -> Self {
+        Self {
+            rustfmt: PathBuf::from("rustfmt"),
+            config: None,
+            cache: PathBuf::from(".codeblock-cache/fmt"),
+            fallback: true,
+        }
+    }
+}
+
+/// Formats Rust code with rustfmt, caching the results.
+///
+/// The formatter is shared by all passes and code blocks, which are processed in parallel.
+#[derive(Debug)]
+pub struct Formatter {
+    rustfmt: PathBuf,
+    /// Path and content of the rustfmt configuration.
+    config: Option<(PathBuf, String)>,
+    /// Directory of the disk cache, `None` to cache in memory only.
+    cache: Option<PathBuf>,
+    fallback: bool,
+    /// Directory rustfmt runs in.
+    directory: PathBuf,
+    /// Output of `rustfmt --version`, looked up on first use.
+    version: OnceLock<String>,
+    memory: Mutex<HashMap<String, String>>,
+    /// Set once rustfmt turned out to be missing, so that this is reported only once.
+    missing: AtomicBool,
+}
+
+impl Default for Formatter {
+    /// Run `rustfmt` from the `PATH` without a configuration file, caching in memory only.
+    fn default() -> Self {
+        let config = FormatConfig::default();
+        Self {
+            rustfmt: config.rustfmt,
+            config: None,
+            cache: None,
+            fallback: config.fallback,
+            directory: PathBuf::from("."),
+            version: OnceLock::new(),
+            memory: Mutex::default(),
+            missing: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Formatter {
+    /// Format as configured, looking up `rustfmt.toml` from `directory` if no configuration file is given.
+    pub fn new(config: FormatConfig, directory: &Path) -> anyhow::Result<Self> {
+        let directory = if directory.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            directory
+        };
+        let path = config.config.or_else(|| find_config(directory));
+        let config_file = path
+            .map(|path| {
+                // rustfmt runs in `directory`, so the path must not be relative.
+                let path = path
+                    .canonicalize()
+                    .with_context(|| format!("Failed to find {}", path.display()))?;
+                std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))
+                    .map(|content| (path, content))
+            })
+            .transpose()?;
+        Ok(Self {
+            rustfmt: config.rustfmt,
+            config: config_file,
+            cache: Some(config.cache),
+            fallback: config.fallback,
+            directory: directory.to_path_buf(),
+            ..Self::default()
+        })
+    }
+
+    /// Format `code` as a Rust file. On failure, print a warning and return the code as it is.
+    pub fn format(&self, code: &str, edition: &str) -> String {
+        self.try_format(code, edition).unwrap_or_else(|error| {
+            eprintln!("Warning: failed to format Rust code: {error:#}");
+            code.to_owned()
+        })
+    }
+
+    /// Format `code` as a Rust file.
+    pub fn try_format(&self, code: &str, edition: &str) -> anyhow::Result<String> {
+        let mut hasher = Sha256::new();
+        let settings = self.config.as_ref().map_or("", |(_, content)| content);
+        for part in [code, edition, self.version(), settings] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        let key = format!("{:x}", hasher.finalize());
+        if let Some(formatted) = self.memory.lock().expect("Poisoned cache").get(&key) {
+            return Ok(formatted.clone());
+        }
+        let path = self
+            .cache
+            .as_ref()
+            .map(|cache| cache.join(format!("{key}.rs")));
+        if let Some(Ok(formatted)) = path.as_ref().map(std::fs::read_to_string) {
+            return Ok(formatted);
+        }
+
+        let Some(formatted) = self.rustfmt(code, edition)? else {
+            if !self.fallback {
+                bail!("{} is not installed", self.rustfmt.display());
+            }
+            if !self.missing.swap(true, Ordering::Relaxed) {
+                eprintln!(
+                    "Warning: {} is not installed, using the builtin formatter",
+                    self.rustfmt.display()
+                );
+            }
+            return builtin_format(code);
+        };
+        if let (Some(cache), Some(path)) = (&self.cache, &path) {
+            if let Err(e) = write_atomically(cache, path, &formatted) {
+                eprintln!("Failed to cache formatted code in {}: {e}", path.display());
+            }
+        }
+        self.memory
+            .lock()
+            .expect("Poisoned cache")
+            .insert(key, formatted.clone());
+        Ok(formatted)
+    }
+
+    /// Format statements or expressions, which are not a valid Rust file on their own, by formatting
+    /// them inside a function.
+    pub fn try_format_fragment(&self, code: &str, edition: &str) -> anyhow::Result<String> {
+        let error = match self.try_format(code, edition) {
+            Ok(formatted) => return Ok(formatted),
+            Err(error) => error,
+        };
+        let wrapped = format!("fn main() {{\n{code}\n}}\n");
+        let Ok(formatted) = self.try_format(&wrapped, edition) else {
+            return Err(error);
+        };
+        match formatted
+            .strip_prefix("fn main() {\n")
+            .and_then(|body| body.strip_suffix("}\n"))
+        {
+            Some(body) => Ok(textwrap::dedent(body)),
+            None if formatted == "fn main() {}\n" => Ok(String::new()),
+            None => Err(error),
+        }
+    }
+
+    /// The version of rustfmt, so that an upgrade does not return results of the previous one.
+    /// Empty if rustfmt cannot be run.
+    fn version(&self) -> &str {
+        self.version.get_or_init(|| {
+            Command::new(&self.rustfmt)
+                .arg("--version")
+                .current_dir(&self.directory)
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_owned())
+                .unwrap_or_default()
+        })
+    }
+
+    /// Run rustfmt. Returns `None` if it is not installed.
+    fn rustfmt(&self, code: &str, edition: &str) -> anyhow::Result<Option<String>> {
+        let mut command = Command::new(&self.rustfmt);
+        command
+            .arg(format!("--edition={edition}"))
+            .current_dir(&self.directory);
+        if let Some((path, _)) = &self.config {
+            command.arg("--config-path").arg(path);
+        }
+        let Some(output) = pipe(&mut command, code)
+            .with_context(|| format!("Failed to run {}", self.rustfmt.display()))?
+        else {
+            return Ok(None);
+        };
+        if !output.status.success() {
+            bail!("{}", String::from_utf8_lossy(&output.stderr).trim_end());
+        }
+        String::from_utf8(output.stdout)
+            .map(Some)
+            .context("rustfmt returned invalid UTF-8")
+    }
+}
+
+/// Run the command with `input` on stdin and collect its output.
+/// Returns `None` if the command is not installed.
+pub(crate) fn pipe(command: &mut Command, input: &str) -> std::io::Result<Option<Output>> {
+    let mut child = match command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        child => child?,
+    };
+    let written = child
+        .stdin
+        .take()
+        .expect("Stdin must be piped")
+        .write_all(input.as_bytes());
+    // A command exiting early is reported by its exit status and error output.
+    match written {
+        Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => return Err(e),
+        _ => {}
+    }
+    child.wait_with_output().map(Some)
+}
+
+/// Look up `rustfmt.toml` or `.rustfmt.toml` in `directory` and its parents.
+fn find_config(directory: &Path) -> Option<PathBuf> {
+    let directory = directory
+        .canonicalize()
+        .unwrap_or_else(|_| directory.to_path_buf());
+    directory.ancestors().find_map(|directory| {
+        ["rustfmt.toml", ".rustfmt.toml"]
+            .into_iter()
+            .map(|name| directory.join(name))
+            .find(|path| path.is_file())
+    })
+}
+
+/// Format with prettyplease. It drops comments other than doc comments, so code with comments is
+/// returned as it is.
+fn builtin_format(code: &str) -> anyhow::Result<String> {
+    let has_comments = code.lines().any(|line| {
+        let trimmed = line.trim_start();
+        (line.contains("//") && !trimmed.starts_with("///") && !trimmed.starts_with("//!"))
+            || line.contains("/*")
+    });
+    if has_comments {
+        return Ok(code.to_owned());
+    }
+    let file = syn::parse_file(code).context("Failed to parse Rust code")?;
+    Ok(prettyplease::unparse(&file))
+}
+
+/// Write the file through a temporary file, so that concurrent readers never see partial content.
+/// The file gets the usual permissions of a new file, not the owner-only ones of temporary files.
+pub(crate) fn write_atomically(directory: &Path, path: &Path, content: &str) -> anyhow::Result<()> {
+    std::fs::create_dir_all(directory)?;
+    #[cfg(unix)]
+    let permissions = {
+        use std::os::unix::fs::PermissionsExt;
+        Some(std::fs::Permissions::from_mode(0o644))
+    };
+    #[cfg(not(unix))]
+    let permissions = None;
+    persist(directory, path, content, permissions)
+}
+
+/// Replace the content of an existing file like [`write_atomically`], keeping its permissions.
+pub fn replace_atomically(path: &Path, content: &str) -> anyhow::Result<()> {
+    let permissions = std::fs::metadata(path)?.permissions();
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    persist(directory, path, content, Some(permissions))
+}
+
+/// Write `content` to a temporary file in `directory` and move it to `path`.
+fn persist(
+    directory: &Path,
+    path: &Path,
+    content: &str,
+    permissions: Option<std::fs::Permissions>,
+) -> anyhow::Result<()> {
+    let mut builder = tempfile::Builder::new();
+    if let Some(permissions) = permissions {
+        builder.permissions(permissions);
+    }
+    let mut file = builder.tempfile_in(directory)?;
+    file.write_all(content.as_bytes())?;
+    file.persist(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn formatter(cache: &Path, rustfmt: &str) -> Formatter {
+        Formatter {
+            rustfmt: PathBuf::from(rustfmt),
+            cache: Some(cache.to_path_buf()),
+            ..Formatter::default()
+        }
+    }
+
+    #[test]
+    fn formats_and_caches() {
+        let cache = tempfile::tempdir().unwrap();
+        let formatter = formatter(cache.path(), "rustfmt");
+        assert_eq!(
+            formatter.try_format("fn  main(){}", "2021").unwrap(),
+            "fn main() {}\n"
+        );
+        let cached = std::fs::read_dir(cache.path())
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(cached.len(), 1);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = cached[0].metadata().unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o644);
+        }
+
+        let error = formatter.try_format("fn main( {}", "2021").unwrap_err();
+        assert!(error.to_string().contains("error"), "{error}");
+
+        assert_eq!(
+            formatter
+                .try_format_fragment("let  a=1;\nlet b =  2;", "2021")
+                .unwrap(),
+            "let a = 1;\nlet b = 2;\n"
+        );
+    }
+
+    #[test]
+    fn respects_rustfmt_toml() {
+        let directory = tempfile::tempdir().unwrap();
+        std::fs::write(directory.path().join("rustfmt.toml"), "hard_tabs = true\n").unwrap();
+        let config = FormatConfig {
+            cache: directory.path().join("cache"),
+            ..FormatConfig::default()
+        };
+        let formatter = Formatter::new(config, directory.path()).unwrap();
+        assert_eq!(
+            formatter.try_format("fn main() { f(); }", "2021").unwrap(),
+            "fn main() {\n\tf();\n}\n"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_builtin_formatter() {
+        let cache = tempfile::tempdir().unwrap();
+        let formatter = formatter(cache.path(), "no-such-rustfmt");
+        assert_eq!(
+            formatter.try_format("fn  main(){}", "2021").unwrap(),
+            "fn main() {}\n"
+        );
+        assert_eq!(
+            formatter.try_format("fn  main(){} // hi", "2021").unwrap(),
+            "fn  main(){} // hi"
+        );
+
+        let formatter = Formatter {
+            fallback: false,
+            ..formatter
+        };
+        assert!(formatter.try_format("fn main() {}", "2021").is_err());
+    }
+
+    #[test]
+    fn replaces_files_keeping_permissions() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("README.md");
+        std::fs::write(&path, "old").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let permissions = std::fs::Permissions::from_mode(0o600);
+            std::fs::set_permissions(&path, permissions).unwrap();
+        }
+        replace_atomically(&path, "new").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! Playground groups: code blocks sharing `playground-group:<name>` only compile together, such as
+//! a struct, its impl and a main function on separate slides. Each of them is sent to the
+//! playground, compiled and run as the whole group.
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, Context};
+
+use crate::codeblock::ProcessedCodeblock;
+
+/// Set the code of the whole group on each code block of a playground group.
+///
+/// The code is taken as filled in by the passes run so far, so that `marker:` blocks can be part of
+/// a group. A problem with a group fails all of its code blocks.
+pub fn assign(results: &mut [anyhow::Result<ProcessedCodeblock>]) {
+    let mut groups = BTreeMap::<String, Vec<usize>>::new();
+    for (index, result) in results.iter().enumerate() {
+        if let Ok(processed) = result {
+            if let Some(name) = processed.codeblock.info.get("playground-group") {
+                groups.entry(name.to_string()).or_default().push(index);
+            }
+        }
+    }
+    for (name, members) in groups {
+        let parts = members
+            .iter()
+            .filter_map(|&index| results[index].as_ref().ok())
+            .map(|processed| {
+                let codeblock = &processed.codeblock;
+                (codeblock.info.get("part"), codeblock.content.as_str())
+            })
+            .collect::<Vec<_>>();
+        let program = concatenate(&name, &parts);
+        for index in members {
+            let Ok(processed) = &mut results[index] else {
+                continue;
+            };
+            match &program {
+                Ok(program) => processed.codeblock.group = Some(program.clone()),
+                Err(error) => {
+                    let location = format!(
+                        "Failed to process code block at line {}:{}",
+                        processed.codeblock.line, processed.codeblock.column
+                    );
+                    results[index] = Err(anyhow!("{error:#}").context(location));
+                }
+            }
+        }
+    }
+}
+
+/// The code of the parts of a group, ordered by their `part:` numbers, or in document order if they
+/// have none.
+pub fn concatenate(name: &str, parts: &[(Option<&str>, &str)]) -> anyhow::Result<String> {
+    let numbered = parts.iter().filter(|(part, _)| part.is_some()).count();
+    let mut ordered = parts
+        .iter()
+        .enumerate()
+        .map(|(index, &(part, code))| {
+            let position = match part {
+                Some(part) => part.parse::<usize>().with_context(|| {
+                    format!("Invalid part `part:{part}` of playground group `{name}`")
+                })?,
+                None => index + 1,
+            };
+            Ok((position, code))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if numbered != 0 && numbered != parts.len() {
+        bail!("Some code blocks of playground group `{name}` have a `part:` and some do not, number all or none");
+    }
+    ordered.sort_by_key(|&(position, _)| position);
+    if let Some(pair) = ordered.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+        bail!(
+            "Playground group `{name}` has several code blocks with `part:{}`",
+            pair[0].0
+        );
+    }
+    Ok(ordered
+        .into_iter()
+        .map(|(_, code)| {
+            let mut code = code.to_string();
+            if !code.ends_with('\n') {
+                code.push('\n');
+            }
+            code
+        })
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn orders_parts() {
+        let code = |parts: &[(Option<&str>, &str)]| concatenate("demo", parts);
+        assert_eq!(
+            code(&[(None, "struct A;"), (None, "fn main() {}\n")]).unwrap(),
+            "struct A;\n\nfn main() {}\n"
+        );
+        assert_eq!(
+            code(&[(Some("2"), "fn main() {}\n"), (Some("1"), "struct A;\n")]).unwrap(),
+            "struct A;\n\nfn main() {}\n"
+        );
+        assert!(code(&[(Some("1"), "a"), (None, "b")]).is_err());
+        assert_eq!(
+            code(&[(Some("1"), "a"), (Some("1"), "b")])
+                .unwrap_err()
+                .to_string(),
+            "Playground group `demo` has several code blocks with `part:1`"
+        );
+        assert!(code(&[(Some("x"), "a")]).is_err());
+    }
+}
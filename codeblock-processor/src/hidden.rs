@@ -0,0 +1,76 @@
+//! Rustdoc-style hidden lines: in Rust code blocks, lines starting with `# ` are not shown,
+//! but are part of the code sent to the playground, compiled and run. `##` escapes a literal `#`.
+
+use snippet_extractor::fence::FenceInfo;
+
+enum Line<'a> {
+    Shown(std::borrow::Cow<'a, str>),
+    Hidden(&'a str),
+}
+
+fn classify(line: &str) -> Line<'_> {
+    let trimmed = line.trim();
+    if trimmed.starts_with("##") {
+        Line::Shown(line.replacen("##", "#", 1).into())
+    } else if let Some(hidden) = trimmed.strip_prefix("# ") {
+        Line::Hidden(hidden)
+    } else if trimmed == "#" {
+        Line::Hidden("")
+    } else {
+        Line::Shown(line.into())
+    }
+}
+
+/// Whether the code block may contain hidden lines.
+pub fn applies(info: &FenceInfo) -> bool {
+    info.language.as_deref() == Some("rust")
+}
+
+/// The complete code, with hidden lines included.
+pub fn full(code: &str) -> String {
+    map_lines(code, |line| match classify(line) {
+        Line::Shown(line) => Some(line.into_owned()),
+        Line::Hidden(line) => Some(line.to_string()),
+    })
+}
+
+/// The code to display, without hidden lines.
+pub fn visible(code: &str) -> String {
+    map_lines(code, |line| match classify(line) {
+        Line::Shown(line) => Some(line.into_owned()),
+        Line::Hidden(_) => None,
+    })
+}
+
+fn map_lines(code: &str, f: impl Fn(&str) -> Option<String>) -> String {
+    code.split_inclusive('\n')
+        .filter_map(|line| {
+            let (content, newline) = match line.strip_suffix('\n') {
+                Some(content) => (content, "\n"),
+                None => (line, ""),
+            };
+            f(content).map(|content| content + newline)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CODE: &str =
+        "# use std::fmt;\n#\n##[derive(Debug)]\nstruct A;\n    # fn hidden() {}\n#[test]\n";
+
+    #[test]
+    fn hides_lines() {
+        assert_eq!(visible(CODE), "#[derive(Debug)]\nstruct A;\n#[test]\n");
+    }
+
+    #[test]
+    fn keeps_hidden_lines_in_full_code() {
+        assert_eq!(
+            full(CODE),
+            "use std::fmt;\n\n#[derive(Debug)]\nstruct A;\nfn hidden() {}\n#[test]\n"
+        );
+    }
+}
@@ -0,0 +1,238 @@
+//! Syntax highlighting at build time, with the syntax definitions and themes bundled with syntect.
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Color, Theme, ThemeSet},
+    html::{styled_line_to_highlighted_html, IncludeBackground},
+    parsing::{ParseState, Scope, ScopeStack, SyntaxSet},
+    util::LinesWithEndings,
+};
+
+use crate::render::escape_html;
+
+/// Settings for syntax highlighting.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HighlightConfig {
+    /// Highlight all code blocks with a known language.
+    pub enabled: bool,
+
+    /// Name of a bundled theme, such as `InspiredGitHub` or `base16-ocean.dark`.
+    pub theme: String,
+
+    /// A `.tmTheme` file, used instead of `theme`. Relative to the configuration file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub theme_file: Option<PathBuf>,
+}
+
+impl Default for HighlightConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            theme: "InspiredGitHub".into(),
+            theme_file: None,
+        }
+    }
+}
+
+/// Classes of highlight.js, which Marp themes such as `rhea.css` style, by the scope they apply to.
+/// The first matching entry wins, so more specific scopes come first.
+const CLASSES: &[(&str, &str)] = &[
+    ("comment", "hljs-comment"),
+    ("string", "hljs-string"),
+    ("constant.numeric", "hljs-number"),
+    ("constant.language", "hljs-literal"),
+    ("entity.name.function", "hljs-title"),
+    ("entity.name.type", "hljs-type"),
+    ("support.type", "hljs-type"),
+    ("storage.type", "hljs-keyword"),
+    ("storage", "hljs-keyword"),
+    ("keyword.operator", "hljs-operator"),
+    ("keyword", "hljs-keyword"),
+    ("support.function", "hljs-built_in"),
+    ("support.macro", "hljs-built_in"),
+    ("meta.annotation", "hljs-meta"),
+    ("meta.attribute", "hljs-meta"),
+    ("variable.parameter", "hljs-params"),
+];
+
+/// Turns code into highlighted HTML, one string per line.
+///
+/// Without CSS classes, the colors of the theme are inlined. With CSS classes, tokens get the
+/// classes of highlight.js, so that the stylesheet of the slides applies.
+#[derive(Debug)]
+pub struct Highlighter {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+    classes: Option<Vec<(Scope, &'static str)>>,
+}
+
+impl Highlighter {
+    pub fn new(config: &HighlightConfig, css_classes: bool) -> anyhow::Result<Self> {
+        let theme = match &config.theme_file {
+            Some(path) => ThemeSet::get_theme(path)
+                .with_context(|| format!("Failed to load theme {}", path.display()))?,
+            None => {
+                let mut themes = ThemeSet::load_defaults().themes;
+                let names = themes.keys().join(", ");
+                themes.remove(&config.theme).ok_or_else(|| {
+                    anyhow!(
+                        "Unknown highlighting theme `{}`, available are: {names}",
+                        config.theme
+                    )
+                })?
+            }
+        };
+        let classes = css_classes.then(|| {
+            CLASSES
+                .iter()
+                .map(|&(scope, class)| (Scope::new(scope).expect("Invalid scope"), class))
+                .collect()
+        });
+        Ok(Self {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme,
+            classes,
+        })
+    }
+
+    /// Inline style for the `<pre>` element with the colors of the theme, if they are inlined.
+    pub fn pre_style(&self) -> Option<String> {
+        if self.classes.is_some() {
+            return None;
+        }
+        let css = |color: Color| format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b);
+        let settings = &self.theme.settings;
+        let style = [
+            settings
+                .background
+                .map(|color| format!("background-color: {};", css(color))),
+            settings
+                .foreground
+                .map(|color| format!("color: {};", css(color))),
+        ]
+        .into_iter()
+        .flatten()
+        .join(" ");
+        (!style.is_empty()).then_some(style)
+    }
+
+    /// Whether there is a syntax definition for the language.
+    pub fn knows(&self, language: &str) -> bool {
+        self.syntaxes.find_syntax_by_token(language).is_some()
+    }
+
+    /// The highlighted HTML of each line of `code`, or `None` if the language is unknown.
+    pub fn lines(&self, language: &str, code: &str) -> anyhow::Result<Option<Vec<String>>> {
+        let Some(syntax) = self.syntaxes.find_syntax_by_token(language) else {
+            return Ok(None);
+        };
+        let mut lines = Vec::new();
+        if let Some(classes) = &self.classes {
+            let mut state = ParseState::new(syntax);
+            let mut stack = ScopeStack::new();
+            for line in LinesWithEndings::from(code) {
+                let mut start = 0;
+                // Adjacent tokens with the same class are merged into one span.
+                let mut spans: Vec<(Option<&str>, String)> = Vec::new();
+                let mut push = |text: &str, stack: &ScopeStack| {
+                    let text = text.trim_end_matches(['\n', '\r']);
+                    if text.is_empty() {
+                        return;
+                    }
+                    let class = class_of(classes, stack);
+                    match spans.last_mut() {
+                        Some((last, content)) if *last == class => content.push_str(text),
+                        _ => spans.push((class, text.to_string())),
+                    }
+                };
+                for (index, operation) in state.parse_line(line, &self.syntaxes)? {
+                    if index > start {
+                        push(&line[start..index], &stack);
+                        start = index;
+                    }
+                    stack.apply(&operation)?;
+                }
+                push(&line[start..], &stack);
+                lines.push(
+                    spans
+                        .into_iter()
+                        .map(|(class, text)| match class {
+                            Some(class) => {
+                                format!("<span class=\"{class}\">{}</span>", escape_html(&text))
+                            }
+                            None => escape_html(&text),
+                        })
+                        .collect(),
+                );
+            }
+        } else {
+            let mut highlighter = HighlightLines::new(syntax, &self.theme);
+            for line in LinesWithEndings::from(code) {
+                let regions = highlighter
+                    .highlight_line(line, &self.syntaxes)?
+                    .into_iter()
+                    .map(|(style, text)| (style, text.trim_end_matches(['\n', '\r'])))
+                    .collect::<Vec<_>>();
+                lines.push(styled_line_to_highlighted_html(
+                    &regions,
+                    IncludeBackground::No,
+                )?);
+            }
+        }
+        Ok(Some(lines))
+    }
+}
+
+/// The class for the innermost scope with one.
+fn class_of(classes: &[(Scope, &'static str)], stack: &ScopeStack) -> Option<&'static str> {
+    stack.as_slice().iter().rev().find_map(|&scope| {
+        classes
+            .iter()
+            .find(|(prefix, _)| prefix.is_prefix_of(scope))
+            .map(|&(_, class)| class)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn highlights_with_classes_or_theme() {
+        let highlighter = Highlighter::new(&HighlightConfig::default(), true).unwrap();
+        let lines = highlighter
+            .lines("rust", "// <hi>\nfn main() {}\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            "<span class=\"hljs-comment\">// &lt;hi&gt;</span>"
+        );
+        assert!(lines[1].starts_with("<span class=\"hljs-keyword\">fn</span>"));
+        assert!(highlighter.lines("no-such-language", "").unwrap().is_none());
+
+        let highlighter = Highlighter::new(&HighlightConfig::default(), false).unwrap();
+        let lines = highlighter
+            .lines("rust", "fn main() {}\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            lines[0],
+            "<span style=\"font-weight:bold;color:#a71d5d;\">fn </span><span style=\"font-weight:bold;color:#795da3;\">main</span><span style=\"color:#323232;\">() {}</span>"
+        );
+        assert!(highlighter.pre_style().is_some());
+
+        let config = HighlightConfig {
+            theme: "nope".into(),
+            ..HighlightConfig::default()
+        };
+        assert!(Highlighter::new(&config, false).is_err());
+    }
+}
@@ -0,0 +1,436 @@
+#![doc = include_str!("../README.md")]
+
+use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::sync::Mutex;
+
+use anyhow::Context;
+use pulldown_cmark::{Event, Parser};
+
+use crate::aggregation::Aggregation;
+use crate::codeblock::{Codeblock, ProcessedCodeblock};
+use crate::codeblock_aggregator::CodeblockTools;
+use crate::pass::CodeblockPass;
+use crate::theme::Theme;
+
+pub mod aggregation;
+pub mod callout;
+pub mod codeblock;
+pub mod codeblock_aggregator;
+pub mod config;
+pub mod format;
+pub mod git;
+pub mod group;
+pub mod hidden;
+pub mod highlight;
+pub mod pass;
+pub mod processor;
+pub mod render;
+pub mod sentinel;
+pub mod slice;
+pub mod theme;
+pub mod update;
+pub mod validate;
+
+/// Options for rendering processed code blocks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// Wrap every code block so that buttons can be placed on it.
+    pub buttons: bool,
+    /// Names of the buttons shown on code blocks without a `buttons:` fence attribute, in order.
+    pub default_buttons: Vec<String>,
+    /// HTML templates for the wrapper and the buttons.
+    pub theme: Theme,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            buttons: true,
+            default_buttons: vec!["playground".into(), "editor".into()],
+            theme: Theme::default(),
+        }
+    }
+}
+
+/// The markdown events of `input`, running `passes` on each fenced code block.
+///
+/// The whole document is parsed and all code blocks are processed, in parallel, before the first
+/// event is returned. The events keep their order.
+pub fn process_codeblocks<'a>(
+    input: &'a str,
+    passes: &'a [Box<dyn CodeblockPass>],
+    options: &'a Options,
+) -> impl Iterator<Item = anyhow::Result<Event<'a>>> + 'a {
+    let mut codeblocks = Vec::new();
+    let aggregations = aggregate(input)
+        .into_iter()
+        .map(|aggregation| match aggregation {
+            Aggregation::Event(event) => Some(event),
+            Aggregation::Codeblock(codeblock) => {
+                codeblocks.push(codeblock);
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    // Generated content is enclosed in sentinel comments carrying the original code block.
+    let sentinels = codeblocks
+        .iter()
+        .map(|codeblock| sentinel::start(sentinel::source(input, &[], codeblock).1))
+        .collect::<Vec<_>>();
+    let mut processed = sentinels
+        .into_iter()
+        .zip(run_all_passes(codeblocks, passes));
+
+    aggregations.into_iter().flat_map(move |aggregation| {
+        let events = match aggregation {
+            Some(event) => Ok(vec![event]),
+            None => {
+                let (start, result) = processed
+                    .next()
+                    .expect("Every code block must be processed");
+                result.map(|processed| generated_events(start, processed, options))
+            }
+        };
+        match events {
+            Ok(events) => events.into_iter().map(Ok).collect::<Vec<_>>(),
+            Err(error) => vec![Err(error)],
+        }
+    })
+}
+
+/// `input` with the output of `passes` in place of each code block, like [`process_codeblocks`],
+/// followed by the scripts. Everything else stays as it is, byte for byte.
+///
+/// Generated content of a previous run is replaced, so that the result is the same as for the
+/// source markdown, see [`sentinel`].
+pub fn process_document(
+    input: &str,
+    passes: &[Box<dyn CodeblockPass>],
+    options: &Options,
+) -> anyhow::Result<String> {
+    let regions = sentinel::regions(input)?;
+    let restored = sentinel::restore(input, &regions);
+    let codeblocks = aggregate(&restored)
+        .into_iter()
+        .filter_map(|aggregation| match aggregation {
+            Aggregation::Codeblock(mut codeblock) => {
+                sentinel::locate(input, &regions, &mut codeblock);
+                Some(codeblock)
+            }
+            Aggregation::Event(_) => None,
+        })
+        .collect::<Vec<_>>();
+    let sentinels = codeblocks
+        .iter()
+        .map(|codeblock| {
+            let (range, source) = sentinel::source(input, &regions, codeblock);
+            (range, sentinel::start(source))
+        })
+        .collect::<Vec<_>>();
+
+    let mut replacements = Vec::new();
+    for ((range, start), result) in sentinels
+        .into_iter()
+        .zip(run_all_passes(codeblocks, passes))
+    {
+        let markdown = render::markdown(generated_events(start, result?, options))?;
+        replacements.push((range.clone(), nest(input, range, &markdown)));
+    }
+    let scripts = regions.iter().filter(|region| region.original.is_none());
+    replacements.extend(scripts.map(|region| (region.range.clone(), String::new())));
+    replacements.sort_by_key(|(range, _)| range.start);
+
+    let mut output = String::new();
+    let mut position = 0;
+    for (range, replacement) in replacements {
+        output.push_str(&input[position..range.start]);
+        output.push_str(&replacement);
+        position = range.end;
+    }
+    output.push_str(&input[position..]);
+    if !output.ends_with('\n') {
+        output.push('\n');
+    }
+    output.push('\n');
+    output.push_str(render::markdown(render::scripts(&options.theme))?.trim_start());
+    Ok(output)
+}
+
+/// All code blocks of `input` and the events between them, each code block knowing its callouts.
+fn aggregate(input: &str) -> Vec<Aggregation<'_>> {
+    let mut aggregations = Parser::new(input)
+        .into_offset_iter()
+        .aggregate_codeblocks(input)
+        .collect::<Vec<_>>();
+    for index in 0..aggregations.len() {
+        let (current, rest) = aggregations.split_at_mut(index + 1);
+        if let Aggregation::Codeblock(codeblock) = &mut current[index] {
+            codeblock.callouts =
+                callout::list_items(rest.iter().map_while(|aggregation| match aggregation {
+                    Aggregation::Event(event) => Some(event),
+                    Aggregation::Codeblock(_) => None,
+                }));
+        }
+    }
+    aggregations
+}
+
+/// The events of a processed code block, enclosed in sentinel comments starting with `start`.
+fn generated_events<'a>(
+    start: String,
+    mut processed: ProcessedCodeblock,
+    options: &Options,
+) -> Vec<Event<'a>> {
+    processed.select_buttons(&options.default_buttons);
+    let mut events = render::html_block([Event::Html(start.into())]);
+    events.extend(processed.into_events(&options.theme, options.buttons));
+    events.extend(render::html_block([Event::Html(sentinel::end().into())]));
+    events
+}
+
+/// `markdown` as a replacement for `range` of `input`, continuing the lists and block quotes the
+/// range is in on every line after the first.
+fn nest(input: &str, range: Range<usize>, markdown: &str) -> String {
+    let line_start = input[..range.start]
+        .rfind('\n')
+        .map_or(0, |newline| newline + 1);
+    let prefix = input[line_start..range.start]
+        .chars()
+        .map(|c| if matches!(c, '>' | '\t') { c } else { ' ' })
+        .collect::<String>();
+    let mut lines = markdown.trim_end_matches('\n').split('\n');
+    let mut output = lines.next().unwrap_or_default().to_string();
+    for line in lines {
+        output.push('\n');
+        if line.is_empty() {
+            output.push_str(prefix.trim_end());
+        } else {
+            output.push_str(&prefix);
+            output.push_str(line);
+        }
+    }
+    if input[range].ends_with('\n') {
+        output.push('\n');
+    }
+    output
+}
+
+/// Run all passes on each code block, spread over as many threads as there are cores.
+/// The results are in the order of the code blocks.
+///
+/// Passes up to the last one which [provides content](CodeblockPass::provides_content) run on all
+/// code blocks first, then the code of playground groups is collected, see [`group`].
+pub fn run_all_passes(
+    codeblocks: Vec<Codeblock>,
+    passes: &[Box<dyn CodeblockPass>],
+) -> Vec<anyhow::Result<ProcessedCodeblock>> {
+    let stage = passes
+        .iter()
+        .rposition(|pass| pass.provides_content())
+        .map_or(0, |index| index + 1);
+    let (providing, remaining) = passes.split_at(stage);
+    let mut results = parallel_map(codeblocks, |codeblock| run_passes(codeblock, providing));
+    group::assign(&mut results);
+    parallel_map(results, |result| {
+        result.and_then(|processed| continue_passes(processed, remaining))
+    })
+}
+
+/// Apply `f` to each item, spread over as many threads as there are cores, keeping the order.
+fn parallel_map<T: Send, R: Send>(items: Vec<T>, f: impl Fn(T) -> R + Sync) -> Vec<R> {
+    let threads = std::thread::available_parallelism()
+        .map_or(1, NonZeroUsize::get)
+        .min(items.len());
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let mut results = std::thread::scope(|scope| {
+        let workers = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let Some((index, item)) = queue.lock().expect("Poisoned queue").next()
+                        else {
+                            break results;
+                        };
+                        results.push((index, f(item)));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect::<Vec<_>>()
+    });
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Run all passes on a code block. Indented code blocks are passed through untouched.
+/// Malformed fence attributes are ignored, see [`validate`] for reporting them.
+pub fn run_passes(
+    codeblock: Codeblock,
+    passes: &[Box<dyn CodeblockPass>],
+) -> anyhow::Result<ProcessedCodeblock> {
+    let processed = ProcessedCodeblock {
+        codeblock,
+        buttons: Vec::new(),
+        before: Vec::new(),
+        after: Vec::new(),
+        blocks: Vec::new(),
+        html: None,
+    };
+    continue_passes(processed, passes)
+}
+
+/// Run more passes on a code block which already went through others.
+pub fn continue_passes(
+    mut processed: ProcessedCodeblock,
+    passes: &[Box<dyn CodeblockPass>],
+) -> anyhow::Result<ProcessedCodeblock> {
+    if processed.codeblock.fence.is_none() {
+        return Ok(processed);
+    }
+    for pass in passes {
+        let codeblock = &processed.codeblock;
+        let result = pass.process(codeblock).with_context(|| {
+            format!(
+                "Failed to process code block at line {}:{}",
+                codeblock.line, codeblock.column
+            )
+        })?;
+        if let Some(fence) = result.fence {
+            processed.codeblock.set_fence(Some(fence));
+        }
+        if let Some(content) = result.content {
+            processed.codeblock.content = content;
+        }
+        processed.buttons.extend(result.buttons);
+        processed.before.extend(result.before);
+        processed.after.extend(result.after);
+        processed.blocks.extend(result.blocks);
+        if let Some(line) = result.source_line {
+            processed.codeblock.source_line = Some(line);
+        }
+        if let Some(html) = result.html {
+            processed.codeblock.rendered = true;
+            processed.html = Some(html);
+        }
+    }
+    Ok(processed)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::pass::{Button, ButtonAction, PassResult};
+
+    use super::*;
+
+    struct Shout;
+
+    impl CodeblockPass for Shout {
+        fn process(&self, codeblock: &Codeblock) -> anyhow::Result<PassResult> {
+            Ok(PassResult {
+                content: Some(codeblock.content.to_uppercase()),
+                ..PassResult::default()
+            })
+        }
+
+        fn provides_content(&self) -> bool {
+            true
+        }
+    }
+
+    struct Program;
+
+    impl CodeblockPass for Program {
+        fn process(&self, codeblock: &Codeblock) -> anyhow::Result<PassResult> {
+            Ok(PassResult {
+                after: vec![codeblock.program().to_string()],
+                ..PassResult::default()
+            })
+        }
+    }
+
+    struct Link;
+
+    impl CodeblockPass for Link {
+        fn process(&self, codeblock: &Codeblock) -> anyhow::Result<PassResult> {
+            if codeblock.content.contains("FAIL") {
+                anyhow::bail!("Refusing to link");
+            }
+            Ok(PassResult {
+                buttons: vec![Button {
+                    name: "editor".into(),
+                    action: ButtonAction::Open(format!(
+                        "'https://example.com/{}'",
+                        codeblock.content.trim()
+                    )),
+                    label: "Link".into(),
+                }],
+                ..PassResult::default()
+            })
+        }
+    }
+
+    #[test]
+    fn passes_see_previous_replacements() {
+        let passes: Vec<Box<dyn CodeblockPass>> = vec![Box::new(Shout), Box::new(Link)];
+        let md = "```text\nhello\n```\n";
+        let options = Options::default();
+        let events = process_codeblocks(md, &passes, &options)
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert!(events.contains(&Event::Text("HELLO\n".into())));
+        assert!(events.iter().any(
+            |event| matches!(event, Event::Html(html) if html.contains("'https://example.com/HELLO'"))
+        ));
+    }
+
+    #[test]
+    fn errors_point_to_the_code_block() {
+        let passes: Vec<Box<dyn CodeblockPass>> = vec![Box::new(Shout), Box::new(Link)];
+        let md = "# Title\n\n```text\nfail\n```\n";
+        let options = Options::default();
+        let error = process_codeblocks(md, &passes, &options)
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Failed to process code block at line 3:1"
+        );
+    }
+
+    #[test]
+    fn playground_groups_see_provided_content() {
+        let passes: Vec<Box<dyn CodeblockPass>> = vec![Box::new(Shout), Box::new(Program)];
+        let md = "```rust playground-group:a part:2\nfn main() {}\n```\n\n```rust playground-group:a part:1\nstruct s;\n```\n\n```rust playground-group:b part:1\n```\n\n```rust playground-group:b part:1\n```\n";
+        let codeblocks = Parser::new(md)
+            .into_offset_iter()
+            .aggregate_codeblocks(md)
+            .filter_map(|aggregation| match aggregation {
+                Aggregation::Codeblock(codeblock) => Some(codeblock),
+                Aggregation::Event(_) => None,
+            })
+            .collect();
+        let results = run_all_passes(codeblocks, &passes);
+        for result in &results[..2] {
+            let processed = result.as_ref().unwrap();
+            assert_eq!(processed.after, ["STRUCT S;\n\nFN MAIN() {}\n"]);
+        }
+        let error = results[3].as_ref().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Failed to process code block at line 12:1"
+        );
+        assert_eq!(
+            error.root_cause().to_string(),
+            "Playground group `b` has several code blocks with `part:1`"
+        );
+    }
+}
@@ -1,16 +1,30 @@
 use anyhow::Context;
 use clap::Parser as ClapParser;
-use processor::playground_button_inserter::PlaygroundButtonInserter;
-use processor::snippet_button_inserter::SnippetButtonInserter;
-use processor::ButtonInserter;
-use pulldown_cmark::{CodeBlockKind, CowStr, Event, Parser, Tag, TagEnd};
-use pulldown_cmark_to_cmark::cmark_with_options;
+use markdown_codeblock_processor::config::Config;
+use markdown_codeblock_processor::format::{self, Formatter};
+use markdown_codeblock_processor::highlight::Highlighter;
+use markdown_codeblock_processor::pass::CodeblockPass;
+use markdown_codeblock_processor::processor::code_formatter::CodeFormatter;
+use markdown_codeblock_processor::processor::copy_button_inserter::CopyButtonInserter;
+use markdown_codeblock_processor::processor::diagram_renderer::DiagramRenderer;
+use markdown_codeblock_processor::processor::diff_inserter::DiffInserter;
+use markdown_codeblock_processor::processor::hidden_line_remover::HiddenLineRemover;
+use markdown_codeblock_processor::processor::line_annotator::{LineAnnotator, LineStyle};
+use markdown_codeblock_processor::processor::playground_button_inserter::{
+    PlaygroundButtonInserter, Template,
+};
+use markdown_codeblock_processor::processor::run_output_inserter::RunOutputInserter;
+use markdown_codeblock_processor::processor::snippet_button_inserter::SnippetButtonInserter;
+use markdown_codeblock_processor::processor::syntax_highlighter::SyntaxHighlighter;
+use markdown_codeblock_processor::processor::target_button_inserter::TargetButtonInserter;
+use markdown_codeblock_processor::theme::{Preset, Theme};
+use markdown_codeblock_processor::validate::Validator;
+use markdown_codeblock_processor::{process_document, sentinel, update, Options};
 use snippet_extractor::Snippets;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::{fs, io::Write};
 
-mod processor;
-
 #[derive(Debug, Clone, ClapParser)]
 struct Arguments {
     #[arg()]
@@ -19,155 +33,235 @@ struct Arguments {
     #[arg(long, default_value_t = true)]
     button: bool,
 
+    /// Buttons shown on code blocks without a `buttons:` fence attribute, in order.
+    /// Available are `playground` (including `playground:<target>`), `editor` and `copy`.
+    #[arg(long, value_delimiter = ',', default_value = "playground,editor")]
+    default_buttons: Vec<String>,
+
     #[arg(short, long)]
     snippets: Option<PathBuf>,
 
+    /// Editor and permalink buttons for `marker:` blocks, overriding `codeblock.toml`.
+    /// Available are `vscode`, `vscode-remote`, `jetbrains`, `zed`, `nvim`, `github` and `gitlab`.
+    #[arg(short, long, value_delimiter = ',')]
+    editor: Option<Vec<String>>,
+
+    /// HTML templates for wrappers and buttons: `marp`, `mdbook` or `plain`, overriding `codeblock.toml`.
+    #[arg(short, long)]
+    theme: Option<Preset>,
+
+    /// Configuration file, `codeblock.toml` in the working directory by default.
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
     #[arg(short, long)]
     output: Option<PathBuf>,
+
+    /// Fail on unknown markers, tags and playground templates and on malformed fence attributes,
+    /// instead of warning about them.
+    #[arg(long)]
+    strict: bool,
+
+    /// Rewrite the code of `marker:` and `include:` blocks in the input file with the current code,
+    /// keeping everything else as it is.
+    #[arg(long, conflicts_with_all = ["strip", "output"])]
+    update: bool,
+
+    /// Like `--update`, but only fail if any code block is out of date, for CI.
+    #[arg(long, conflicts_with_all = ["update", "strip", "output"])]
+    check: bool,
+
+    /// Remove everything a previous run generated and print the source markdown.
+    #[arg(long)]
+    strip: bool,
+
+    /// Highlight code blocks at build time, with the theme from `codeblock.toml`.
+    #[arg(long)]
+    highlight: bool,
 }
 
 fn main() -> anyhow::Result<()> {
     let args = Arguments::parse();
 
+    let mut config = Config::load(args.config.as_deref())?;
+    if let Some(editors) = args.editor {
+        config.editor.targets = editors;
+    }
+    if let Some(preset) = args.theme {
+        config.theme.preset = preset;
+    }
+    if args.highlight {
+        config.highlight.enabled = true;
+    }
+    let theme = Theme::load(&config.theme).context("Failed to load theme")?;
+    let line_style = config.lines.style.unwrap_or(match config.theme.preset {
+        Preset::Mdbook => LineStyle::Fence,
+        Preset::Marp | Preset::Plain => LineStyle::Html,
+    });
+
+    let mut validator = Validator::default()
+        .with_templates(Template::with_builtin(config.templates.clone()).keys());
     let snippets = if let Some(snippets) = args.snippets {
         let snippets = fs::read_to_string(snippets).context("Failed to load snippets")?;
-        serde_json::from_str::<Snippets>(&snippets).context("Failed to parse snippets")?
+        let snippets = Snippets::from_json(&snippets).context("Failed to parse snippets")?;
+        validator = validator.with_snippets(&snippets);
+        snippets
     } else {
         Snippets::default()
     };
 
-    let input = fs::read_to_string(args.input).context("Failed to open input file")?;
-
-    let parser = Parser::new(&input);
+    let input_directory = args
+        .input
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let include_base = config
+        .include
+        .base
+        .clone()
+        .unwrap_or_else(|| input_directory.clone());
+    let formatter =
+        Arc::new(Formatter::new(config.fmt, &input_directory).context("Failed to set up rustfmt")?);
 
-    let snippet_inserter = SnippetButtonInserter::with_snippets(snippets);
-    let playground_inserter = PlaygroundButtonInserter;
-
-    let mut current_url = None;
-    let mut current_block = None;
-    let mut current_fence = None;
-    let mut current_btn_text = None;
+    let input = fs::read_to_string(&args.input).context("Failed to open input file")?;
+    // Processing the output of a previous run replaces what it generated.
+    let generated = sentinel::contains(&input);
+    let stripped = sentinel::strip(&input).with_context(|| {
+        format!(
+            "Failed to strip generated content from {}",
+            args.input.display()
+        )
+    })?;
+    if args.strip {
+        return write_output(args.output.as_deref(), &stripped);
+    }
 
-    let i = parser.collect::<Vec<_>>();
-    let mut document = Vec::with_capacity(i.len());
+    let diagnostics = validator.validate(&input);
+    let severity = if args.strict { "error" } else { "warning" };
+    for diagnostic in &diagnostics {
+        eprintln!(
+            "{}:{}:{}: {severity}: {}",
+            args.input.display(),
+            diagnostic.line,
+            diagnostic.column,
+            diagnostic.message
+        );
+    }
+    if args.strict && !diagnostics.is_empty() {
+        anyhow::bail!(
+            "Found {} problems in {}",
+            diagnostics.len(),
+            args.input.display()
+        );
+    }
 
-    for event in i {
-        match event.clone() {
-            Event::Start(Tag::CodeBlock(kind)) => {
-                if let CodeBlockKind::Fenced(text) = kind {
-                    current_fence = Some(text);
-                }
-                if args.button {
-                    document.push(Event::Html("<div style=\"position: relative;\">".into()));
-                }
-                document.push(event);
-            }
-            Event::Text(ref code) => {
-                if let Some(ref fence) = current_fence.take() {
-                    snippet_inserter.handle_codeblock(
-                        fence,
-                        code,
-                        &mut current_block,
-                        &mut current_url,
-                        &mut current_btn_text,
-                    );
-                    playground_inserter.handle_codeblock(
-                        fence,
-                        code,
-                        &mut current_block,
-                        &mut current_url,
-                        &mut current_btn_text,
-                    );
-                }
-
-                let event = Event::Text(
-                    current_block
-                        .take()
-                        .map_or_else(|| code.clone(), CowStr::from),
+    let snippet_inserter = SnippetButtonInserter::with_snippets(snippets.clone())
+        .with_include_base(include_base)
+        .with_editors(config.editor)
+        .context("Failed to set up editor buttons")?;
+    if args.update || args.check {
+        if generated {
+            anyhow::bail!(
+                "{} is generated output, update the source markdown instead",
+                args.input.display()
+            );
+        }
+        let update = update::update(&input, &snippet_inserter)?;
+        let severity = if args.check { "error" } else { "note" };
+        for stale in &update.stale {
+            eprintln!(
+                "{}:{}:{}: {severity}: {}",
+                args.input.display(),
+                stale.line,
+                stale.column,
+                stale.message
+            );
+        }
+        if args.check {
+            if !update.stale.is_empty() {
+                anyhow::bail!(
+                    "{} code blocks in {} are out of date, run with --update",
+                    update.stale.len(),
+                    args.input.display()
                 );
-                document.push(event);
-            }
-            Event::End(TagEnd::CodeBlock) => {
-                document.push(event);
-                document.push(Event::Text("\n".into()));
-                if args.button {
-                    if let Some(url) = current_url.take() {
-                        let btn_text = current_btn_text.take().unwrap_or_default();
-                        let button = make_button(url, &btn_text);
-                        document.extend(button.into_iter());
-                    }
-                    document.push(Event::Text("\n".into()));
-                    document.push(Event::Html("</div>\n\n".into()));
-                }
             }
-            _ => document.push(event),
-        };
+        } else if !update.stale.is_empty() {
+            format::replace_atomically(&args.input, &update.output)
+                .with_context(|| format!("Failed to write {}", args.input.display()))?;
+            eprintln!(
+                "Updated {} code blocks in {}",
+                update.stale.len(),
+                args.input.display()
+            );
+        }
+        return Ok(());
     }
 
-    document.push(Event::Text("\n".into()));
-    document.push(Event::Html(include_str!("make_path.html").into()));
-    document.push(Event::Text("\n".into()));
+    let diff_inserter = DiffInserter::new(snippets, snippet_inserter.editor_links().clone())
+        .with_style(line_style)
+        .with_css_classes(config.theme.css_classes);
 
-    document.push(Event::Html(include_str!("blur_button.html").into()));
-    document.push(Event::Text("\n".into()));
+    let highlighter = config
+        .highlight
+        .enabled
+        .then(|| Highlighter::new(&config.highlight, config.theme.css_classes).map(Arc::new))
+        .transpose()
+        .context("Failed to set up syntax highlighting")?;
+    let mut line_annotator = LineAnnotator::default()
+        .with_style(line_style)
+        .with_css_classes(config.theme.css_classes);
+    if let Some(highlighter) = &highlighter {
+        line_annotator = line_annotator.with_highlighter(highlighter.clone());
+    }
 
-    let mut output = String::with_capacity(input.len() + 1000);
+    let mut passes: Vec<Box<dyn CodeblockPass>> = vec![
+        Box::new(snippet_inserter),
+        Box::new(diff_inserter),
+        Box::new(CodeFormatter::new(formatter.clone())),
+        Box::new(
+            PlaygroundButtonInserter::with_templates(config.templates.clone())
+                .with_formatter(formatter.clone()),
+        ),
+        Box::new(
+            RunOutputInserter::default()
+                .with_templates(config.templates.clone())
+                .with_config(config.run),
+        ),
+        Box::new(
+            TargetButtonInserter::default()
+                .with_templates(config.templates)
+                .with_godbolt(config.godbolt)
+                .with_targets(config.targets)
+                .with_formatter(formatter),
+        ),
+        Box::new(CopyButtonInserter),
+        Box::new(DiagramRenderer::new(config.diagrams, &input_directory)),
+        Box::new(HiddenLineRemover),
+    ];
+    if let Some(highlighter) = highlighter {
+        passes.push(Box::new(
+            SyntaxHighlighter::new(highlighter).with_css_classes(config.theme.css_classes),
+        ));
+    }
+    passes.push(Box::new(line_annotator));
 
-    let options = pulldown_cmark_to_cmark::Options {
-        list_token: '-',
-        ..Default::default()
+    let options = Options {
+        buttons: args.button,
+        default_buttons: args.default_buttons,
+        theme,
     };
-    let _state = cmark_with_options(&mut document.into_iter(), &mut output, options)?;
 
-    if let Some(path) = args.output {
+    let output = process_document(&input, &passes, &options)
+        .with_context(|| format!("Failed to process {}", args.input.display()))?;
+    write_output(args.output.as_deref(), &output)
+}
+
+fn write_output(path: Option<&Path>, output: &str) -> anyhow::Result<()> {
+    if let Some(path) = path {
         std::fs::write(path, output)?;
     } else {
         let mut stdout = std::io::stdout();
         stdout.write_all(output.as_bytes())?;
     }
-
     Ok(())
 }
-
-fn make_button<'a>(url: String, btn_text: &str) -> Vec<Event<'a>> {
-    let mut button = Vec::new();
-    button.push(Event::Html("<p style=\"position: absolute; right: 10px; top: 10px; padding: 0; margin: 0; line-height: 0\">\n".into()));
-    button.push(Event::Html("<button\n    onclick=\"window.open(".into()));
-    button.push(Event::Html(url.into()));
-    button.push(Event::Html(",'_blank')\"\n".into()));
-    button.push(Event::Html("    style=\"\n".into()));
-    button.push(Event::Html("    height: fit-content;\n".into()));
-    button.push(Event::Html("    margin: 0;\n".into()));
-    button.push(Event::Html("    font-weight: bold;\"\n".into()));
-    button.push(Event::Html(format!(">{btn_text}\n").into()));
-    button.push(Event::Html("</button>\n".into()));
-    button.push(Event::Html("</p>\n".into()));
-    button
-}
-
-#[cfg(test)]
-mod test {
-    use pulldown_cmark_to_cmark::cmark;
-
-    use crate::make_button;
-
-    #[test]
-    fn makes_button() {
-        let mut output = String::new();
-        let button = make_button("'https://www.example.com'".to_string(), "Example.com!");
-        let _state = cmark(&mut button.into_iter(), &mut output).unwrap();
-
-        let expected = r#"<p style="position: absolute; right: 10px; top: 10px; padding: 0; margin: 0; line-height: 0">
-<button
-    onclick="window.open('https://www.example.com','_blank')"
-    style="
-    height: fit-content;
-    margin: 0;
-    font-weight: bold;"
->Example.com!
-</button>
-</p>
-"#;
-        assert_eq!(expected, output);
-    }
-}
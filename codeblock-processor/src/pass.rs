@@ -0,0 +1,67 @@
+use crate::codeblock::Codeblock;
+
+/// A button rendered in the top right corner of a code block.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Button {
+    /// Name for selecting the button with the `buttons:` fence attribute, such as `playground`.
+    pub name: String,
+    /// What happens on click.
+    pub action: ButtonAction,
+    /// Text on the button.
+    pub label: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ButtonAction {
+    /// Open a URL, given as JavaScript expression such as `'https://example.com'`.
+    Open(String),
+    /// Copy the text of the code block to the clipboard.
+    Copy,
+}
+
+/// A code block inserted after the processed one, such as the output of running it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExtraBlock {
+    /// Info string of the fence, such as `text`.
+    pub fence: String,
+    pub content: String,
+}
+
+/// The outcome of running a [`CodeblockPass`] on a code block.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PassResult {
+    /// Replacement for the fence info string.
+    pub fence: Option<String>,
+    /// Replacement for the content of the code block.
+    pub content: Option<String>,
+    /// Buttons to add to the code block.
+    pub buttons: Vec<Button>,
+    /// HTML to insert before the code block.
+    pub before: Vec<String>,
+    /// HTML to insert after the code block.
+    pub after: Vec<String>,
+    /// Code blocks to insert after the code block, before `after`.
+    pub blocks: Vec<ExtraBlock>,
+    /// 1-based line in the source file where the content starts, for content read from a file.
+    pub source_line: Option<usize>,
+    /// HTML rendering the code block itself, replacing the markdown code block.
+    pub html: Option<String>,
+}
+
+/// A pass over each fenced code block of a markdown document.
+///
+/// Passes run in order. Each pass sees the fence and content as replaced by the previous passes,
+/// buttons and extra HTML of all passes are collected.
+/// Code blocks are processed in parallel, so passes must be `Send + Sync`.
+pub trait CodeblockPass: Send + Sync {
+    /// Process a code block. Errors should describe what is wrong with the block,
+    /// the location in the markdown document is added by the caller.
+    fn process(&self, codeblock: &Codeblock) -> anyhow::Result<PassResult>;
+
+    /// Whether the pass fills in or rewrites the code of blocks, which other blocks may depend on,
+    /// such as the blocks of a playground group. Passes up to the last such pass run on all code
+    /// blocks before the remaining passes.
+    fn provides_content(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+use anyhow::{bail, Context};
+
+use crate::{
+    codeblock::Codeblock,
+    format::Formatter,
+    hidden,
+    pass::{CodeblockPass, PassResult},
+};
+
+/// Formats the displayed code of Rust code blocks marked with `fmt`.
+///
+/// Code which is not a valid Rust file on its own, such as a few statements, is formatted as the body
+/// of a function. The edition is taken from `playground-edition`, `2021` by default.
+#[derive(Debug, Default)]
+pub struct CodeFormatter {
+    formatter: Arc<Formatter>,
+}
+
+impl CodeFormatter {
+    pub fn new(formatter: Arc<Formatter>) -> Self {
+        Self { formatter }
+    }
+}
+
+impl CodeblockPass for CodeFormatter {
+    fn process(&self, codeblock: &Codeblock) -> anyhow::Result<PassResult> {
+        let info = &codeblock.info;
+        if !info.has("fmt") {
+            return Ok(PassResult::default());
+        }
+        if !matches!(info.language.as_deref(), Some("rust") | None) {
+            bail!("Only Rust code blocks can be formatted");
+        }
+        if hidden::full(&codeblock.content) != codeblock.content {
+            bail!("Code blocks with hidden lines cannot be formatted");
+        }
+        let edition = info.get("playground-edition").unwrap_or("2021");
+        let formatted = self
+            .formatter
+            .try_format_fragment(&codeblock.content, edition)
+            .context("Failed to format code")?;
+        Ok(PassResult {
+            content: Some(formatted),
+            ..PassResult::default()
+        })
+    }
+
+    fn provides_content(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn formats_displayed_code() {
+        let pass = CodeFormatter::default();
+        let codeblock = Codeblock::new(Some("rust fmt".into()), "let  a=[1,2];\n".into(), 0..0, "");
+        assert_eq!(
+            pass.process(&codeblock).unwrap().content.as_deref(),
+            Some("let a = [1, 2];\n")
+        );
+
+        let hidden = Codeblock::new(Some("rust fmt".into()), "# fn f() {}\n".into(), 0..0, "");
+        assert!(pass.process(&hidden).is_err());
+    }
+}
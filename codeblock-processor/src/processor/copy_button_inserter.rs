@@ -0,0 +1,22 @@
+use crate::{
+    codeblock::Codeblock,
+    pass::{Button, ButtonAction, CodeblockPass, PassResult},
+};
+
+/// Offers a copy-to-clipboard button on every fenced code block.
+/// It is only shown where selected, see [`crate::Options::default_buttons`].
+#[derive(Debug, Default)]
+pub struct CopyButtonInserter;
+
+impl CodeblockPass for CopyButtonInserter {
+    fn process(&self, _codeblock: &Codeblock) -> anyhow::Result<PassResult> {
+        Ok(PassResult {
+            buttons: vec![Button {
+                name: "copy".into(),
+                action: ButtonAction::Copy,
+                label: "Copy".into(),
+            }],
+            ..PassResult::default()
+        })
+    }
+}
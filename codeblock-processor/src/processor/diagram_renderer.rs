@@ -0,0 +1,332 @@
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::Mutex,
+};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use snippet_extractor::paths::relative_to;
+
+use crate::{
+    codeblock::Codeblock,
+    format::{pipe, write_atomically},
+    pass::{CodeblockPass, PassResult},
+    render::escape_html,
+};
+
+/// Settings for rendering diagram code blocks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiagramConfig {
+    /// Directory for the rendered SVG files, named by the hash of the source.
+    /// Relative to the configuration file, `diagrams` next to the markdown document by default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assets: Option<PathBuf>,
+
+    /// How diagrams are embedded when the fence does not say: `inline` or `link`.
+    pub embed: Embed,
+
+    /// The Graphviz executable, for `dot` blocks.
+    pub dot: PathBuf,
+
+    /// The PlantUML executable, for `plantuml` blocks.
+    pub plantuml: PathBuf,
+
+    /// The Mermaid CLI, for `mermaid` blocks.
+    pub mmdc: PathBuf,
+}
+
+impl Default for DiagramConfig {
+    fn default() -> Self {
+        Self {
+            assets: None,
+            embed: Embed::Inline,
+            dot: PathBuf::from("dot"),
+            plantuml: PathBuf::from("plantuml"),
+            mmdc: PathBuf::from("mmdc"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Embed {
+    /// Insert the SVG into the document.
+    Inline,
+    /// Insert an image linking to the SVG file in the assets directory.
+    Link,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Renderer {
+    Dot,
+    PlantUml,
+    Mermaid,
+}
+
+impl Renderer {
+    fn of_language(language: &str) -> Option<Self> {
+        match language {
+            "dot" | "graphviz" => Some(Self::Dot),
+            "plantuml" | "puml" => Some(Self::PlantUml),
+            "mermaid" => Some(Self::Mermaid),
+            _ => None,
+        }
+    }
+}
+
+/// Replaces `dot`, `plantuml` and `mermaid` code blocks with the rendered SVG.
+///
+/// The source stays available in a collapsible section below the diagram. `diagram:link` or
+/// `diagram:inline` select how the SVG is embedded, `diagram:none` keeps the code block.
+/// If the renderer is not installed, the code block is kept and a warning is printed.
+#[derive(Debug)]
+pub struct DiagramRenderer {
+    config: DiagramConfig,
+    /// Directory of the SVG files.
+    assets: PathBuf,
+    /// Directory of the markdown document, which image links are relative to.
+    document: PathBuf,
+    /// Renderers which turned out to be missing, to warn only once about each.
+    missing: Mutex<Vec<PathBuf>>,
+}
+
+impl DiagramRenderer {
+    /// Render diagrams for the markdown document in `document`.
+    pub fn new(config: DiagramConfig, document: &Path) -> Self {
+        let assets = config
+            .assets
+            .clone()
+            .unwrap_or_else(|| document.join("diagrams"));
+        Self {
+            config,
+            assets,
+            document: document.to_path_buf(),
+            missing: Mutex::default(),
+        }
+    }
+
+    fn executable(&self, renderer: Renderer) -> &Path {
+        match renderer {
+            Renderer::Dot => &self.config.dot,
+            Renderer::PlantUml => &self.config.plantuml,
+            Renderer::Mermaid => &self.config.mmdc,
+        }
+    }
+
+    /// The SVG file of the diagram, rendering it if it is not cached yet.
+    /// Returns `None` if the renderer is not installed.
+    fn svg_file(&self, renderer: Renderer, source: &str) -> anyhow::Result<Option<PathBuf>> {
+        let executable = self.executable(renderer);
+        let mut hasher = Sha256::new();
+        for part in [&executable.display().to_string(), source] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        let path = self.assets.join(format!("{:x}.svg", hasher.finalize()));
+        if path.exists() {
+            return Ok(Some(path));
+        }
+        let svg = match renderer {
+            Renderer::Dot => render_piped(Command::new(executable).arg("-Tsvg"), source)?,
+            Renderer::PlantUml => {
+                let source = if source.trim_start().starts_with("@start") {
+                    source.to_string()
+                } else {
+                    format!("@startuml\n{source}@enduml\n")
+                };
+                render_piped(Command::new(executable).args(["-tsvg", "-pipe"]), &source)?
+            }
+            Renderer::Mermaid => mermaid(executable, source)?,
+        };
+        let Some(svg) = svg else {
+            let mut missing = self.missing.lock().expect("Poisoned renderer list");
+            if !missing.iter().any(|missing| missing == executable) {
+                eprintln!(
+                    "Warning: {} is not installed, diagrams are shown as code",
+                    executable.display()
+                );
+                missing.push(executable.to_path_buf());
+            }
+            return Ok(None);
+        };
+        write_atomically(&self.assets, &path, &svg)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(Some(path))
+    }
+}
+
+/// Run the renderer with the source on stdin and return its stdout.
+/// Returns `None` if the renderer is not installed.
+fn render_piped(command: &mut Command, source: &str) -> anyhow::Result<Option<String>> {
+    let Some(output) = pipe(command, source).context("Failed to run renderer")? else {
+        return Ok(None);
+    };
+    if !output.status.success() {
+        bail!(
+            "Failed to render diagram:\n{}",
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        );
+    }
+    Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
+}
+
+/// The Mermaid CLI reads and writes files.
+fn mermaid(executable: &Path, source: &str) -> anyhow::Result<Option<String>> {
+    let directory = tempfile::tempdir().context("Failed to create temporary directory")?;
+    let input = directory.path().join("diagram.mmd");
+    let output = directory.path().join("diagram.svg");
+    std::fs::write(&input, source)?;
+    let result = match Command::new(executable)
+        .arg("-i")
+        .arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .stdin(Stdio::null())
+        .output()
+    {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        result => result.context("Failed to run renderer")?,
+    };
+    if !result.status.success() {
+        bail!(
+            "Failed to render diagram:\n{}",
+            String::from_utf8_lossy(&result.stderr).trim_end()
+        );
+    }
+    std::fs::read_to_string(&output)
+        .map(Some)
+        .context("Failed to read rendered diagram")
+}
+
+/// The `<svg>` element of an SVG file, without XML declaration and doctype, and without blank lines,
+/// which would end the HTML block in markdown.
+fn inline_svg(svg: &str) -> String {
+    let svg = svg.find("<svg").map_or(svg, |start| &svg[start..]);
+    let mut html = svg
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    html.push('\n');
+    html
+}
+
+impl CodeblockPass for DiagramRenderer {
+    fn process(&self, codeblock: &Codeblock) -> anyhow::Result<PassResult> {
+        let info = &codeblock.info;
+        let Some(language) = info.language.as_deref() else {
+            return Ok(PassResult::default());
+        };
+        let Some(renderer) = Renderer::of_language(language) else {
+            return Ok(PassResult::default());
+        };
+        let embed = match info.get("diagram") {
+            None => self.config.embed,
+            Some("inline") => Embed::Inline,
+            Some("link") => Embed::Link,
+            Some("none") => return Ok(PassResult::default()),
+            Some(other) => {
+                bail!("Unknown diagram embedding `diagram:{other}`, use `inline`, `link` or `none`")
+            }
+        };
+        let Some(path) = self.svg_file(renderer, &codeblock.content)? else {
+            return Ok(PassResult::default());
+        };
+
+        let diagram = match embed {
+            Embed::Inline => inline_svg(
+                &std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?,
+            ),
+            Embed::Link => {
+                let link = relative_to(&path, &self.document)
+                    .display()
+                    .to_string()
+                    .replace('\\', "/");
+                format!(
+                    "<img src=\"{}\" alt=\"{language} diagram\">\n",
+                    escape_html(&link)
+                )
+            }
+        };
+        // Newlines are escaped, since a blank line would end the HTML block.
+        let source = escape_html(&codeblock.content).replace('\n', "&#10;");
+        Ok(PassResult {
+            html: Some(format!(
+                "{diagram}<details>\n<summary>Source</summary>\n<pre><code class=\"language-{language}\">{source}</code></pre>\n</details>\n"
+            )),
+            ..PassResult::default()
+        })
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    fn codeblock(fence: &str) -> Codeblock {
+        Codeblock::new(Some(fence.into()), "digraph { a -> b }\n".into(), 0..0, "")
+    }
+
+    /// A fake `dot` printing an SVG and counting its runs.
+    fn fake_dot(directory: &Path) -> PathBuf {
+        let path = directory.join("dot");
+        std::fs::write(
+            &path,
+            format!(
+                "#!/bin/sh\necho run >> {}/runs\nprintf '<?xml version=\"1.0\"?>\\n<svg>\\n\\n<g/></svg>\\n'\n",
+                directory.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[test]
+    fn renders_and_caches_svg() {
+        let directory = tempfile::tempdir().unwrap();
+        let config = DiagramConfig {
+            dot: fake_dot(directory.path()),
+            ..DiagramConfig::default()
+        };
+        let pass = DiagramRenderer::new(config, directory.path());
+
+        let html = pass.process(&codeblock("dot")).unwrap().html.unwrap();
+        assert!(html.starts_with("<svg>\n<g/></svg>\n<details>"), "{html}");
+        assert!(html.contains("digraph { a -&gt; b }&#10;"));
+
+        let html = pass
+            .process(&codeblock("dot diagram:link"))
+            .unwrap()
+            .html
+            .unwrap();
+        assert!(html.starts_with("<img src=\"diagrams/"), "{html}");
+        let runs = std::fs::read_to_string(directory.path().join("runs")).unwrap();
+        assert_eq!(runs.lines().count(), 1);
+
+        assert_eq!(
+            pass.process(&codeblock("dot diagram:none")).unwrap(),
+            PassResult::default()
+        );
+    }
+
+    #[test]
+    fn keeps_code_without_renderer() {
+        let directory = tempfile::tempdir().unwrap();
+        let config = DiagramConfig {
+            dot: PathBuf::from("no-such-dot"),
+            ..DiagramConfig::default()
+        };
+        let pass = DiagramRenderer::new(config, directory.path());
+        assert_eq!(
+            pass.process(&codeblock("dot")).unwrap(),
+            PassResult::default()
+        );
+    }
+}
@@ -0,0 +1,394 @@
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use itertools::{EitherOrBoth, Itertools};
+use similar::{DiffTag, TextDiff};
+use snippet_extractor::{parser::parse, Snippets};
+
+use crate::{
+    codeblock::Codeblock,
+    git::file_at_revision,
+    pass::{CodeblockPass, PassResult},
+    processor::{
+        line_annotator::LineStyle,
+        snippet_button_inserter::{display_content, fence_with_language, EditorLinks, Location},
+    },
+    render::escape_html,
+};
+
+/// A line of a diff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Line<'a> {
+    Equal(&'a str),
+    Remove(&'a str),
+    Add(&'a str),
+}
+
+/// One side of a diff: a snippet, possibly at a git revision.
+#[derive(Debug)]
+struct Side {
+    name: String,
+    content: String,
+    location: Location,
+}
+
+/// Renders the diff between two snippets selected with `diff:before..after`.
+///
+/// Either side may name a git revision, as in `diff:snippet@v1..snippet@v2`. A side starting with `@`
+/// uses the snippet of the other side, so `diff:snippet@v1..@HEAD` works as well. Without a revision,
+/// the snippet is taken from the current snippets.
+///
+/// `diff-style:side-by-side` renders a table instead of a unified diff. It needs HTML, in markdown
+/// mode the diff is always unified.
+#[derive(Debug)]
+pub struct DiffInserter {
+    snippets: Snippets,
+    links: EditorLinks,
+    style: LineStyle,
+    css_classes: bool,
+}
+
+impl DiffInserter {
+    pub fn new(snippets: Snippets, links: EditorLinks) -> Self {
+        Self {
+            snippets,
+            links,
+            style: LineStyle::Html,
+            css_classes: false,
+        }
+    }
+
+    /// With [`LineStyle::Fence`], the diff is a `diff` code block instead of HTML.
+    pub fn with_style(mut self, style: LineStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Emit CSS classes (`diff-add`, `diff-remove`) instead of inline styles.
+    pub fn with_css_classes(mut self, css_classes: bool) -> Self {
+        self.css_classes = css_classes;
+        self
+    }
+
+    fn side(&self, spec: &str, other: &str) -> anyhow::Result<Side> {
+        let (marker, revision) = match spec.split_once('@') {
+            Some(("", revision)) => (other.split('@').next().unwrap_or_default(), Some(revision)),
+            Some((marker, revision)) => (marker, Some(revision)),
+            None => (spec, None),
+        };
+        let Some(snippet) = self.snippets.snippets_for_id(marker).into_iter().next() else {
+            bail!("Unknown marker `{marker}`");
+        };
+        let path = self.snippets.absolute_path(&snippet);
+        let Some(revision) = revision else {
+            return Ok(Side {
+                name: spec.to_string(),
+                location: Location::of_snippet(path, &snippet),
+                content: snippet.content,
+            });
+        };
+        let (text, commit) = file_at_revision(&path, revision)?;
+        let Some(snippet) = parse(&text, Path::new(snippet.file.as_str())).remove(marker) else {
+            bail!(
+                "Marker `{marker}` not found in {} at `{revision}`",
+                path.display()
+            );
+        };
+        Ok(Side {
+            name: format!("{marker}@{revision}"),
+            location: Location {
+                commit: Some(commit),
+                ..Location::of_snippet(path, &snippet)
+            },
+            content: snippet.content,
+        })
+    }
+
+    fn line_html(&self, line: Line) -> String {
+        let (prefix, text, class, style) = match line {
+            Line::Equal(text) => (" ", text, "line", ""),
+            Line::Remove(text) => ("-", text, "line diff-remove", REMOVE_STYLE),
+            Line::Add(text) => ("+", text, "line diff-add", ADD_STYLE),
+        };
+        let attributes = if self.css_classes {
+            format!(" class=\"{class}\"")
+        } else if style.is_empty() {
+            String::new()
+        } else {
+            format!(" style=\"{style}\"")
+        };
+        format!("<span{attributes}>{prefix}{}</span>", escape_html(text))
+    }
+
+    fn unified_html(&self, lines: &[Line]) -> String {
+        let mut html = String::from("<pre><code class=\"language-diff\">");
+        for &line in lines {
+            html.push_str(&self.line_html(line));
+            html.push('\n');
+        }
+        html.push_str("</code></pre>\n");
+        html
+    }
+
+    fn side_by_side_html(&self, lines: &[Line], before: &str, after: &str) -> String {
+        let table = if self.css_classes {
+            "<table class=\"diff\">"
+        } else {
+            "<table style=\"width: 100%; table-layout: fixed; border-collapse: collapse;\">"
+        };
+        let cell = if self.css_classes {
+            "<td>"
+        } else {
+            "<td style=\"vertical-align: top; padding: 0;\">"
+        };
+        let mut html = format!(
+            "{table}\n<tr><th>{}</th><th>{}</th></tr>\n",
+            escape_html(before),
+            escape_html(after)
+        );
+        let column = |line: Option<Line>| match line {
+            Some(line) => format!(
+                "{cell}<pre><code>{}</code></pre></td>",
+                self.line_html(line)
+            ),
+            None => format!("{cell}</td>"),
+        };
+        for (left, right) in side_by_side_rows(lines) {
+            html.push_str(&format!("<tr>{}{}</tr>\n", column(left), column(right)));
+        }
+        html.push_str("</table>\n");
+        html
+    }
+}
+
+const REMOVE_STYLE: &str =
+    "display: inline-block; width: 100%; background-color: rgba(255, 0, 0, 0.2);";
+const ADD_STYLE: &str =
+    "display: inline-block; width: 100%; background-color: rgba(0, 200, 0, 0.2);";
+
+/// The lines of both texts, marked as equal, removed or added, with the whole text as context.
+fn diff_lines<'a>(before: &'a str, after: &'a str) -> Vec<Line<'a>> {
+    let old = before.lines().collect::<Vec<_>>();
+    let new = after.lines().collect::<Vec<_>>();
+    let diff = TextDiff::from_slices(&old, &new);
+    let mut lines = Vec::new();
+    for op in diff.ops() {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+        match tag {
+            DiffTag::Equal => lines.extend(old[old_range].iter().copied().map(Line::Equal)),
+            DiffTag::Delete => lines.extend(old[old_range].iter().copied().map(Line::Remove)),
+            DiffTag::Insert => lines.extend(new[new_range].iter().copied().map(Line::Add)),
+            DiffTag::Replace => {
+                lines.extend(old[old_range].iter().copied().map(Line::Remove));
+                lines.extend(new[new_range].iter().copied().map(Line::Add));
+            }
+        }
+    }
+    lines
+}
+
+/// Pair up the lines for two columns. Removed lines are shown next to the lines added in their place.
+fn side_by_side_rows<'a>(lines: &[Line<'a>]) -> Vec<(Option<Line<'a>>, Option<Line<'a>>)> {
+    let mut rows = Vec::new();
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    fn flush<'a>(
+        removed: &mut Vec<Line<'a>>,
+        added: &mut Vec<Line<'a>>,
+        rows: &mut Vec<(Option<Line<'a>>, Option<Line<'a>>)>,
+    ) {
+        rows.extend(
+            removed
+                .drain(..)
+                .zip_longest(added.drain(..))
+                .map(|pair| match pair {
+                    EitherOrBoth::Both(left, right) => (Some(left), Some(right)),
+                    EitherOrBoth::Left(left) => (Some(left), None),
+                    EitherOrBoth::Right(right) => (None, Some(right)),
+                }),
+        );
+    }
+    for &line in lines {
+        match line {
+            Line::Remove(_) => removed.push(line),
+            Line::Add(_) => added.push(line),
+            Line::Equal(_) => {
+                flush(&mut removed, &mut added, &mut rows);
+                rows.push((Some(line), Some(line)));
+            }
+        }
+    }
+    flush(&mut removed, &mut added, &mut rows);
+    rows
+}
+
+/// The lines in `diff` syntax.
+fn unified_text(lines: &[Line]) -> String {
+    lines
+        .iter()
+        .map(|line| match line {
+            Line::Equal(text) => format!(" {text}\n"),
+            Line::Remove(text) => format!("-{text}\n"),
+            Line::Add(text) => format!("+{text}\n"),
+        })
+        .collect()
+}
+
+impl CodeblockPass for DiffInserter {
+    fn process(&self, codeblock: &Codeblock) -> anyhow::Result<PassResult> {
+        let info = &codeblock.info;
+        let Some(spec) = info.get("diff") else {
+            return Ok(PassResult::default());
+        };
+        let Some((before, after)) = spec.split_once("..") else {
+            bail!("Invalid diff `{spec}`, expected `diff:before..after`");
+        };
+        let side_by_side = match info.get("diff-style") {
+            None | Some("unified") => false,
+            Some("side-by-side") => true,
+            Some(other) => bail!("Unknown diff style `{other}`, use `unified` or `side-by-side`"),
+        };
+        let before = self
+            .side(before, after)
+            .with_context(|| format!("Failed to read `{before}`"))?;
+        let after = self
+            .side(after, before.name.as_str())
+            .with_context(|| format!("Failed to read `{after}`"))?;
+
+        let old = display_content(info, &before.content);
+        let new = display_content(info, &after.content);
+        let lines = diff_lines(&old, &new);
+        let text = unified_text(&lines);
+
+        let mut buttons = self.links.buttons(&before.location, Some(&before.name));
+        buttons.extend(self.links.buttons(&after.location, Some(&after.name)));
+
+        let fence = codeblock.fence.as_deref().unwrap_or_default();
+        let mut result = PassResult {
+            fence: fence_with_language(fence, info, "diff"),
+            content: Some(text),
+            buttons,
+            ..PassResult::default()
+        };
+        if self.style == LineStyle::Html {
+            result.html = Some(if side_by_side {
+                self.side_by_side_html(&lines, &before.name, &after.name)
+            } else {
+                self.unified_html(&lines)
+            });
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::BTreeMap, path::PathBuf};
+
+    use snippet_extractor::Snippet;
+
+    use super::*;
+    use crate::pass::ButtonAction;
+
+    fn snippets() -> Snippets {
+        let snippet = |content: &str, line| Snippet {
+            content: content.into(),
+            file: "src/lib.rs".into(),
+            line,
+            col: 3,
+            language: Some("rust".into()),
+        };
+        Snippets {
+            base: Some(PathBuf::from("/project")),
+            snippets: BTreeMap::from([(
+                PathBuf::from("src/lib.rs"),
+                BTreeMap::from([
+                    ("before".to_string(), snippet("fn a() {\n    1\n}\n", 0)),
+                    ("after".to_string(), snippet("fn a() {\n    2\n}\n", 10)),
+                ]),
+            )]),
+        }
+    }
+
+    fn codeblock(fence: &str) -> Codeblock {
+        Codeblock::new(Some(fence.into()), String::new(), 0..0, "")
+    }
+
+    #[test]
+    fn renders_unified_diffs() {
+        let pass =
+            DiffInserter::new(snippets(), EditorLinks::default()).with_style(LineStyle::Fence);
+        let result = pass.process(&codeblock("diff:before..after")).unwrap();
+        assert_eq!(result.fence.as_deref(), Some("diff diff:before..after"));
+        assert_eq!(
+            result.content.as_deref(),
+            Some(" fn a() {\n-    1\n+    2\n }\n")
+        );
+        assert_eq!(result.html, None);
+
+        let urls = result
+            .buttons
+            .iter()
+            .map(|button| match &button.action {
+                ButtonAction::Open(url) => (button.label.as_str(), url.as_str()),
+                ButtonAction::Copy => panic!("Editor buttons must open a URL"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                (
+                    "Open VSCode (before)",
+                    "'vscode://file//project/src/lib.rs:1:3'"
+                ),
+                (
+                    "Open VSCode (after)",
+                    "'vscode://file//project/src/lib.rs:11:3'"
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn renders_html() {
+        let pass = DiffInserter::new(snippets(), EditorLinks::default()).with_css_classes(true);
+        let html = pass
+            .process(&codeblock("diff diff:before..after"))
+            .unwrap()
+            .html
+            .unwrap();
+        assert_eq!(
+            html,
+            "<pre><code class=\"language-diff\">\
+            <span class=\"line\"> fn a() {</span>\n\
+            <span class=\"line diff-remove\">-    1</span>\n\
+            <span class=\"line diff-add\">+    2</span>\n\
+            <span class=\"line\"> }</span>\n\
+            </code></pre>\n"
+        );
+
+        let html = pass
+            .process(&codeblock(
+                "diff diff:before..after diff-style:side-by-side",
+            ))
+            .unwrap()
+            .html
+            .unwrap();
+        assert!(!html.contains("\n\n"));
+        assert_eq!(html.matches("<tr>").count(), 4);
+        assert!(html.contains(
+            "<tr><td><pre><code><span class=\"line diff-remove\">-    1</span></code></pre></td>\
+            <td><pre><code><span class=\"line diff-add\">+    2</span></code></pre></td></tr>"
+        ));
+    }
+
+    #[test]
+    fn unknown_markers_are_errors() {
+        let pass = DiffInserter::new(snippets(), EditorLinks::default());
+        let error = pass
+            .process(&codeblock("diff diff:before..nope"))
+            .unwrap_err();
+        assert_eq!(error.root_cause().to_string(), "Unknown marker `nope`");
+        assert!(pass.process(&codeblock("diff diff:before")).is_err());
+    }
+}
@@ -0,0 +1,24 @@
+use crate::{
+    codeblock::Codeblock,
+    hidden,
+    pass::{CodeblockPass, PassResult},
+};
+
+/// Removes rustdoc-style hidden lines from the displayed code, see [`crate::hidden`].
+///
+/// Must run after the passes which use the complete code, such as the playground buttons.
+#[derive(Debug, Default)]
+pub struct HiddenLineRemover;
+
+impl CodeblockPass for HiddenLineRemover {
+    fn process(&self, codeblock: &Codeblock) -> anyhow::Result<PassResult> {
+        if !hidden::applies(&codeblock.info) {
+            return Ok(PassResult::default());
+        }
+        let visible = hidden::visible(&codeblock.content);
+        Ok(PassResult {
+            content: (visible != codeblock.content).then_some(visible),
+            ..PassResult::default()
+        })
+    }
+}
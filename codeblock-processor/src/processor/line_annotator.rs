@@ -0,0 +1,375 @@
+use std::{collections::BTreeSet, sync::Arc};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    callout,
+    codeblock::Codeblock,
+    highlight::Highlighter,
+    pass::{CodeblockPass, PassResult},
+    render::{escape_attribute, escape_html},
+};
+
+/// How highlighted lines and line numbers are expressed in the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineStyle {
+    /// Replace the code block with HTML, one span per line. For Marp and plain HTML.
+    Html,
+    /// Keep the code block and put `linenos`, `linenostart` and `hl_lines` into the fence,
+    /// as understood by mdBook preprocessors, Zola and Hugo.
+    Fence,
+}
+
+/// Settings for `highlight:` and `linenos`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LinesConfig {
+    /// `fence` for the `mdbook` theme preset, `html` otherwise.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub style: Option<LineStyle>,
+}
+
+/// Highlights lines selected with `highlight:3-5,9`, numbers lines with `linenos` and renders
+/// callouts as badges.
+///
+/// Line numbers start at `linenos:<start>`, or at the source line of code read from a file.
+/// `highlight-source:12-14` selects lines by their source line number instead.
+#[derive(Debug)]
+pub struct LineAnnotator {
+    style: LineStyle,
+    css_classes: bool,
+    highlighter: Option<Arc<Highlighter>>,
+}
+
+impl Default for LineAnnotator {
+    fn default() -> Self {
+        Self {
+            style: LineStyle::Html,
+            css_classes: false,
+            highlighter: None,
+        }
+    }
+}
+
+impl LineAnnotator {
+    pub fn with_style(mut self, style: LineStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Emit CSS classes (`line`, `line-highlight`, `line-number`) instead of inline styles.
+    pub fn with_css_classes(mut self, css_classes: bool) -> Self {
+        self.css_classes = css_classes;
+        self
+    }
+
+    /// Highlight the syntax of the lines. The lines are then always rendered as HTML.
+    pub fn with_highlighter(mut self, highlighter: Arc<Highlighter>) -> Self {
+        self.highlighter = Some(highlighter);
+        self
+    }
+
+    fn html(
+        &self,
+        codeblock: &Codeblock,
+        highlighted: &BTreeSet<usize>,
+        start: Option<usize>,
+    ) -> anyhow::Result<String> {
+        let language = codeblock.info.language.as_deref().unwrap_or("text");
+        let (code, callouts): (Vec<_>, Vec<_>) =
+            codeblock.content.lines().map(callout::split).unzip();
+        let mut content = code.join("\n");
+        if codeblock.content.ends_with('\n') {
+            content.push('\n');
+        }
+        let lines = match &self.highlighter {
+            Some(highlighter) => highlighter.lines(language, &content)?,
+            None => None,
+        };
+        let lines = lines.unwrap_or_else(|| content.lines().map(escape_html).collect());
+        let mut pre = match self.highlighter.as_ref().and_then(|h| h.pre_style()) {
+            Some(style) => format!("<pre style=\"{style}\""),
+            None => "<pre".to_string(),
+        };
+        // Line numbers and callout badges are part of the text of the lines, so the copy button
+        // copies the code from an attribute instead.
+        if start.is_some() || callouts.iter().any(|numbers| !numbers.is_empty()) {
+            pre.push_str(&format!(" data-code=\"{}\"", escape_attribute(&content)));
+        }
+        pre.push('>');
+        let mut html = format!("{pre}<code class=\"language-{language}\">");
+        for (index, line) in lines.iter().enumerate() {
+            let highlight = highlighted.contains(&(index + 1));
+            let attributes = match (self.css_classes, highlight) {
+                (true, true) => " class=\"line line-highlight\"",
+                (true, false) => " class=\"line\"",
+                (false, true) => " style=\"display: inline-block; width: 100%; background-color: rgba(255, 200, 0, 0.3);\"",
+                (false, false) => "",
+            };
+            html.push_str(&format!("<span{attributes}>"));
+            if let Some(start) = start {
+                let number = start + index;
+                if self.css_classes {
+                    html.push_str(&format!("<span class=\"line-number\">{number}</span>"));
+                } else {
+                    html.push_str(&format!(
+                        "<span style=\"display: inline-block; min-width: 2.5em; padding-right: 1em; text-align: right; opacity: 0.5; user-select: none;\">{number}</span>"
+                    ));
+                }
+            }
+            html.push_str(line);
+            for &number in callouts.get(index).into_iter().flatten() {
+                html.push_str(&self.badge(number, &codeblock.callouts[number - 1]));
+            }
+            html.push_str("</span>\n");
+        }
+        html.push_str("</code></pre>\n");
+        Ok(html)
+    }
+
+    /// A callout badge, with the explaining list item as tooltip.
+    fn badge(&self, number: usize, item: &str) -> String {
+        let title = escape_attribute(item);
+        if self.css_classes {
+            format!("<span class=\"callout\" title=\"{title}\">{number}</span>")
+        } else {
+            format!(
+                "<span title=\"{title}\" style=\"display: inline-block; min-width: 1.4em; margin-left: 0.5em; border-radius: 0.7em; background-color: #333; color: #fff; font-size: 0.8em; text-align: center; user-select: none;\">{number}</span>"
+            )
+        }
+    }
+
+    /// Render the code block as HTML, with its syntax highlighted. Used by [`SyntaxHighlighter`].
+    ///
+    /// [`SyntaxHighlighter`]: crate::processor::syntax_highlighter::SyntaxHighlighter
+    pub(crate) fn plain_html(&self, codeblock: &Codeblock) -> anyhow::Result<String> {
+        self.html(codeblock, &BTreeSet::new(), None)
+    }
+}
+
+/// Parse a list of lines and ranges like `3-5,9` into line numbers.
+pub fn parse_lines(spec: &str) -> anyhow::Result<BTreeSet<usize>> {
+    let number = |number: &str| -> anyhow::Result<usize> {
+        number
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid line number `{number}` in `{spec}`"))
+    };
+    let mut lines = BTreeSet::new();
+    for part in spec.split(',').filter(|part| !part.trim().is_empty()) {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (number(start)?, number(end)?);
+                if end < start {
+                    bail!("Invalid line range `{part}` in `{spec}`");
+                }
+                lines.extend(start..=end);
+            }
+            None => {
+                lines.insert(number(part)?);
+            }
+        }
+    }
+    Ok(lines)
+}
+
+/// Format line numbers as compact ranges, like `3-5 9`.
+fn format_ranges(lines: &BTreeSet<usize>) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &line in lines {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == line => *end = line,
+            _ => ranges.push((line, line)),
+        }
+    }
+    ranges
+        .into_iter()
+        .map(|(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{start}-{end}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether the code block has line annotations or callouts, which [`LineAnnotator`] renders.
+pub(crate) fn annotates(codeblock: &Codeblock) -> bool {
+    let info = &codeblock.info;
+    info.has("highlight")
+        || info.has("highlight-source")
+        || info.has("linenos")
+        || !callout::numbers(&codeblock.content).is_empty()
+}
+
+impl CodeblockPass for LineAnnotator {
+    fn process(&self, codeblock: &Codeblock) -> anyhow::Result<PassResult> {
+        let info = &codeblock.info;
+        // Diagrams and diffs are kept as rendered.
+        if codeblock.rendered || !annotates(codeblock) {
+            return Ok(PassResult::default());
+        }
+        callout::check(&codeblock.content, &codeblock.callouts)?;
+        let annotated =
+            info.has("highlight") || info.has("highlight-source") || info.has("linenos");
+
+        let mut highlighted = match info.get("highlight") {
+            Some(spec) => parse_lines(spec)?,
+            None => BTreeSet::new(),
+        };
+        if let Some(spec) = info.get("highlight-source") {
+            let Some(source_line) = codeblock.source_line else {
+                bail!(
+                    "`highlight-source` needs code read line by line from a file, with `marker:` or `include:` and without `collapse:`, `elide-bodies` or `hide_other_markers`"
+                );
+            };
+            for line in parse_lines(spec)? {
+                if line < source_line {
+                    bail!("Source line {line} is before the code block, which starts at line {source_line}");
+                }
+                highlighted.insert(line - source_line + 1);
+            }
+        }
+        let line_count = codeblock.content.lines().count();
+        if let Some(line) = highlighted
+            .iter()
+            .find(|&&line| line == 0 || line > line_count)
+        {
+            bail!("Cannot highlight line {line}, the code block has {line_count} lines");
+        }
+
+        let start = if info.has("linenos") {
+            let start = match info.get("linenos") {
+                Some(start) => start
+                    .parse()
+                    .with_context(|| format!("Invalid start line `linenos:{start}`"))?,
+                None => codeblock.source_line.unwrap_or(1),
+            };
+            Some(start)
+        } else {
+            None
+        };
+
+        let style = match self.highlighter {
+            Some(_) => LineStyle::Html,
+            None => self.style,
+        };
+        Ok(match style {
+            LineStyle::Html => PassResult {
+                html: Some(self.html(codeblock, &highlighted, start)?),
+                ..PassResult::default()
+            },
+            // Callouts stay comments in the code.
+            LineStyle::Fence if !annotated => PassResult::default(),
+            LineStyle::Fence => {
+                let mut info = info.clone();
+                info.attributes.retain(|attribute| {
+                    !matches!(
+                        attribute.key.as_str(),
+                        "highlight" | "highlight-source" | "linenos"
+                    )
+                });
+                let mut fence = info.to_string();
+                if let Some(start) = start {
+                    fence.push_str(&format!(" linenos linenostart={start}"));
+                }
+                if !highlighted.is_empty() {
+                    fence.push_str(&format!(" hl_lines=\"{}\"", format_ranges(&highlighted)));
+                }
+                PassResult {
+                    fence: Some(fence.trim_start().to_string()),
+                    ..PassResult::default()
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn codeblock(fence: &str) -> Codeblock {
+        Codeblock::new(Some(fence.into()), "a\n<b>\nc\n".into(), 0..0, "")
+    }
+
+    #[test]
+    fn parses_line_lists() {
+        assert_eq!(
+            parse_lines("3-5,9")
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            [3, 4, 5, 9]
+        );
+        assert!(parse_lines("5-3").is_err());
+        assert!(parse_lines("x").is_err());
+    }
+
+    #[test]
+    fn renders_html_lines() {
+        let pass = LineAnnotator::default().with_css_classes(true);
+        let html = pass
+            .process(&codeblock("rust highlight:2 linenos:10"))
+            .unwrap()
+            .html
+            .unwrap();
+        assert_eq!(
+            html,
+            "<pre data-code=\"a&#10;&lt;b&gt;&#10;c&#10;\"><code class=\"language-rust\">\
+            <span class=\"line\"><span class=\"line-number\">10</span>a</span>\n\
+            <span class=\"line line-highlight\"><span class=\"line-number\">11</span>&lt;b&gt;</span>\n\
+            <span class=\"line\"><span class=\"line-number\">12</span>c</span>\n\
+            </code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn translates_source_lines() {
+        let pass = LineAnnotator::default().with_style(LineStyle::Fence);
+        let mut block = codeblock("rust marker:a highlight-source:21-22 linenos");
+        block.source_line = Some(20);
+        let fence = pass.process(&block).unwrap().fence.unwrap();
+        assert_eq!(
+            fence,
+            "rust marker:a linenos linenostart=20 hl_lines=\"2-3\""
+        );
+
+        let block = codeblock("rust highlight:4");
+        assert!(pass.process(&block).is_err());
+    }
+
+    #[test]
+    fn keeps_rendered_blocks() {
+        let pass = LineAnnotator::default();
+        let mut block = codeblock("diff highlight:1 linenos");
+        block.rendered = true;
+        assert_eq!(pass.process(&block).unwrap(), PassResult::default());
+    }
+
+    #[test]
+    fn renders_callout_badges() {
+        let pass = LineAnnotator::default().with_css_classes(true);
+        let mut block = Codeblock::new(
+            Some("rust".into()),
+            "let a = 1; // <1>\nlet b = a;\n".into(),
+            0..0,
+            "",
+        );
+        assert!(pass.process(&block).is_err());
+
+        block.callouts = vec!["The \"first\" one".into()];
+        let html = pass.process(&block).unwrap().html.unwrap();
+        assert!(html.starts_with("<pre data-code=\"let a = 1;&#10;let b = a;&#10;\">"));
+        assert!(html.contains(
+            "<span class=\"line\">let a = 1;<span class=\"callout\" title=\"The &quot;first&quot; one\">1</span></span>\n"
+        ));
+
+        let pass = pass.with_style(LineStyle::Fence);
+        assert_eq!(pass.process(&block).unwrap(), PassResult::default());
+    }
+}
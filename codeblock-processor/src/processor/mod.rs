@@ -1,15 +1,11 @@
-use pulldown_cmark::CowStr;
-
+pub mod code_formatter;
+pub mod copy_button_inserter;
+pub mod diagram_renderer;
+pub mod diff_inserter;
+pub mod hidden_line_remover;
+pub mod line_annotator;
 pub mod playground_button_inserter;
+pub mod run_output_inserter;
 pub mod snippet_button_inserter;
-
-pub trait ButtonInserter {
-    fn handle_codeblock(
-        &self,
-        fence: &CowStr,
-        code: &CowStr,
-        current_block: &mut Option<String>,
-        current_url: &mut Option<String>,
-        current_btn_text: &mut Option<String>,
-    );
-}
+pub mod syntax_highlighter;
+pub mod target_button_inserter;
@@ -1,150 +1,274 @@
-use std::{
-    io::Write,
-    process::{Command, Stdio},
-};
+use std::{collections::BTreeMap, sync::Arc};
 
+use anyhow::Context;
 use itertools::Itertools;
-use pulldown_cmark::CowStr;
 use serde::{Deserialize, Serialize};
-use take_until::TakeUntilExt;
+use snippet_extractor::fence::FenceInfo;
 use urlencoding::encode;
 
-use super::ButtonInserter;
+use crate::{
+    callout,
+    codeblock::Codeblock,
+    format::Formatter,
+    hidden,
+    pass::{Button, ButtonAction, CodeblockPass, PassResult},
+};
 
+/// Code wrapped around a code block before sending it to the playground,
+/// referenced by name with `playground-wrap:<name>`.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Template {
-    pre: String,
-    post: String,
-}
+    /// Prelude, inserted before the code. Should end with a newline.
+    #[serde(default)]
+    pub pre: String,
 
-#[derive(Debug, Default)]
-pub struct PlaygroundButtonInserter;
-
-impl ButtonInserter for PlaygroundButtonInserter {
-    fn handle_codeblock(
-        &self,
-        fence: &CowStr,
-        code: &CowStr,
-        _current_block: &mut Option<String>,
-        current_url: &mut Option<String>,
-        current_btn_text: &mut Option<String>,
-    ) {
-        let main_template = Template {
-            pre: String::from("fn main() {"),
-            post: String::from("}"),
-        };
-        let main_anyhow_template = Template {
-            pre: String::from("fn main() -> anyhow::Result<()> {"),
-            post: String::from("Ok(())\n}"),
-        };
-        let tokio_main_anyhow_template = Template {
-            pre: String::from("#[tokio::main]\nasync fn main() -> anyhow::Result<()> {"),
-            post: String::from("Ok(())\n}"),
-        };
+    /// Postlude, inserted after the code.
+    #[serde(default)]
+    pub post: String,
+
+    /// Indent the code by this many spaces.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub indent: Option<usize>,
 
-        let tag = fence
-            .split_whitespace()
-            .filter(|s| s.starts_with("tag:"))
-            .collect::<Vec<_>>()
-            .pop();
-        if tag != Some("tag:playground-button") {
-            return;
+    /// Rust edition, `2021` by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edition: Option<String>,
+
+    /// Release channel, `stable` by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+}
+
+impl Template {
+    fn new(pre: &str, post: &str) -> Self {
+        Self {
+            pre: pre.to_string(),
+            post: post.to_string(),
+            indent: None,
+            edition: None,
+            channel: None,
         }
+    }
 
-        let template = if fence.split_whitespace().contains(&"playground-wrap:main") {
-            main_template
-        } else if fence
-            .split_whitespace()
-            .contains(&"playground-wrap:main_anyhow")
-        {
-            main_anyhow_template
-        } else if fence
-            .split_whitespace()
-            .contains(&"playground-wrap:main_tokio_anyhow")
-        {
-            tokio_main_anyhow_template
-        } else {
-            if fence.split_whitespace().contains(&"playground-wrap:") {
-                eprintln!("Warning: unknown playground wrap marker in \"{fence}\"");
-            }
-            let before = fence
-                .split_whitespace()
-                .skip_while(|elem| !elem.starts_with("playground-before:$\""))
-                .take_until(|elem| elem.ends_with("\"$"))
-                .join(" ");
-            let after = fence
-                .split_whitespace()
-                .skip_while(|elem| !elem.starts_with("playground-after:"))
-                .take_until(|elem| elem.ends_with("\"$"))
-                .join(" ");
-            let before = before.replace("playground-before:$\"", "");
-            let before = before.replace("\"$", "");
-            let after = after.replace("playground-after:$\"", "");
-            let after = after.replace("\"$", "");
-
-            Template {
-                pre: before,
-                post: after,
-            }
-        };
+    /// The templates which are always available: `main`, `main_anyhow` and `main_tokio_anyhow`.
+    pub fn builtin() -> BTreeMap<String, Template> {
+        BTreeMap::from([
+            ("main".into(), Template::new("fn main() {", "}")),
+            (
+                "main_anyhow".into(),
+                Template::new("fn main() -> anyhow::Result<()> {", "Ok(())\n}"),
+            ),
+            (
+                "main_tokio_anyhow".into(),
+                Template::new(
+                    "#[tokio::main]\nasync fn main() -> anyhow::Result<()> {",
+                    "Ok(())\n}",
+                ),
+            ),
+        ])
+    }
 
-        let before = template.pre;
-        let after = template.post;
-        let code = if fence.split_whitespace().contains(&"playground-indent") {
-            textwrap::indent(code, "    ")
-        } else {
-            code.to_string()
-        };
+    /// The builtin templates, extended and overridden by `templates`.
+    pub fn with_builtin(templates: BTreeMap<String, Template>) -> BTreeMap<String, Template> {
+        let mut all = Self::builtin();
+        all.extend(templates);
+        all
+    }
+}
+
+#[derive(Debug)]
+pub struct PlaygroundButtonInserter {
+    templates: BTreeMap<String, Template>,
+    formatter: Arc<Formatter>,
+}
 
-        let channel = fence
-            .split_whitespace()
-            .find(|s| s.starts_with("playground-channel:"))
-            .unwrap_or("stable");
-        let channel = channel.replace("playground-channel:", "");
+impl Default for PlaygroundButtonInserter {
+    fn default() -> Self {
+        Self {
+            templates: Template::builtin(),
+            formatter: Arc::default(),
+        }
+    }
+}
+
+impl PlaygroundButtonInserter {
+    /// Use these templates in addition to the builtin ones, replacing builtin templates of the same name.
+    pub fn with_templates(templates: BTreeMap<String, Template>) -> Self {
+        Self {
+            templates: Template::with_builtin(templates),
+            ..Self::default()
+        }
+    }
+
+    /// Format the playground code with this formatter, shared with other passes.
+    pub fn with_formatter(mut self, formatter: Arc<Formatter>) -> Self {
+        self.formatter = formatter;
+        self
+    }
+}
 
-        let text = format!("{before}{code}{after}");
+impl CodeblockPass for PlaygroundButtonInserter {
+    fn process(&self, codeblock: &Codeblock) -> anyhow::Result<PassResult> {
+        let info = &codeblock.info;
+        let code = codeblock.program();
 
-        let text = text.replace("\\n", "\n");
+        if info.get("tag") != Some("playground-button") {
+            return Ok(PassResult::default());
+        }
 
-        let text = format_rust_code_block(&text);
+        let wrapped = wrap(&self.templates, info, code)?;
+        let channel = &wrapped.channel;
+        let edition = &wrapped.edition;
+        let text = self.formatter.format(&wrapped.code, edition);
 
         let text = encode(&text);
         let text = format!(
-            "'https://play.rust-lang.org/?version={channel}&mode=debug&edition=2021&code={text}'"
+            "'https://play.rust-lang.org/?version={channel}&mode=debug&edition={edition}&code={text}'"
         );
 
-        *current_url = Some(text);
-        *current_btn_text = Some("Playground".into());
+        Ok(PassResult {
+            buttons: vec![Button {
+                name: "playground".into(),
+                action: ButtonAction::Open(text),
+                label: "Playground".into(),
+            }],
+            ..PassResult::default()
+        })
     }
 }
 
-fn format_rust_code_block(code: &str) -> String {
-    // Run the rustfmt command line tool on the code block
-    let output = Command::new("rustfmt")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .arg("--edition=2021")
-        .spawn()
-        .and_then(|mut child| {
-            child
-                .stdin
-                .as_mut()
-                .expect("Failed to get stdin of child process")
-                .write_all(code.as_bytes())?;
-            let output = child.wait_with_output()?;
-            Ok(output.stdout)
-        });
-
-    // Return the formatted code as a String, or the original code block if there was an error
-    match output {
-        Ok(vec) if vec.is_empty() => {
-            eprintln!("Failed to format Rust code.");
-            code.to_owned()
-        }
-        Ok(formatted_output) => String::from_utf8_lossy(&formatted_output).into_owned(),
-        Err(e) => {
-            eprintln!("Failed to format Rust code: {e:?}");
-            code.to_owned()
-        }
+/// The code of a block wrapped in its playground template, with the edition and channel to use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wrapped {
+    pub code: String,
+    pub edition: String,
+    pub channel: String,
+}
+
+/// Wrap `code` as selected by the `playground-wrap`, `playground-before`, `playground-after`,
+/// `playground-indent`, `playground-edition` and `playground-channel` fence attributes.
+/// Hidden lines are included and callout comments removed, see [`crate::hidden`] and [`crate::callout`].
+pub fn wrap(
+    templates: &BTreeMap<String, Template>,
+    info: &FenceInfo,
+    code: &str,
+) -> anyhow::Result<Wrapped> {
+    let code = &callout::strip(&if hidden::applies(info) {
+        hidden::full(code)
+    } else {
+        code.to_string()
+    });
+    let template = match info.get("playground-wrap") {
+        Some(name) => templates.get(name).cloned().with_context(|| {
+            format!(
+                "Unknown playground template `{name}`, available are: {}",
+                templates.keys().join(", ")
+            )
+        })?,
+        None => Template::new(
+            info.get("playground-before").unwrap_or_default(),
+            info.get("playground-after").unwrap_or_default(),
+        ),
+    };
+
+    let before = &template.pre;
+    let after = &template.post;
+    let indent = if info.has("playground-indent") {
+        Some(4)
+    } else {
+        template.indent
+    };
+    let code = match indent {
+        Some(indent) => textwrap::indent(code, &" ".repeat(indent)),
+        None => code.to_string(),
+    };
+
+    let channel = info
+        .get("playground-channel")
+        .or(template.channel.as_deref())
+        .unwrap_or("stable");
+    let edition = info
+        .get("playground-edition")
+        .or(template.edition.as_deref())
+        .unwrap_or("2021");
+
+    let text = format!("{before}{code}{after}");
+
+    Ok(Wrapped {
+        code: text.replace("\\n", "\n"),
+        edition: edition.to_string(),
+        channel: channel.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn codeblock(fence: &str, content: &str) -> Codeblock {
+        Codeblock::new(Some(fence.into()), content.into(), 0..0, "")
+    }
+
+    #[test]
+    fn tag_inside_quoted_value_is_ignored() {
+        let pass = PlaygroundButtonInserter::default();
+        let quoted = codeblock(
+            r#"rust title:"not a tag:playground-button""#,
+            "fn main() {}\n",
+        );
+        assert!(pass.process(&quoted).unwrap().buttons.is_empty());
+
+        let tagged = codeblock(
+            r#"rust title:"tag:other" tag:playground-button"#,
+            "fn main() {}\n",
+        );
+        let buttons = pass.process(&tagged).unwrap().buttons;
+        assert_eq!(buttons.len(), 1);
+        let ButtonAction::Open(url) = &buttons[0].action else {
+            panic!("Playground button must open a URL");
+        };
+        assert!(url.starts_with("'https://play.rust-lang.org/?version=stable"));
+    }
+
+    #[test]
+    fn uses_configured_templates() {
+        let template = Template {
+            pre: "use std::fmt;\nfn main() {\n".into(),
+            post: "}\n".into(),
+            indent: Some(2),
+            edition: Some("2018".into()),
+            channel: Some("nightly".into()),
+        };
+        let pass = PlaygroundButtonInserter::with_templates(BTreeMap::from([(
+            "fmt".to_string(),
+            template,
+        )]));
+        let block = codeblock(
+            "rust tag:playground-button playground-wrap:fmt",
+            "let a = 1;\n",
+        );
+        let buttons = pass.process(&block).unwrap().buttons;
+        let ButtonAction::Open(url) = &buttons[0].action else {
+            panic!("Playground button must open a URL");
+        };
+        assert!(url.contains("version=nightly"));
+        assert!(url.contains("edition=2018"));
+        assert!(url.contains("use%20std%3A%3Afmt%3B"));
+    }
+
+    #[test]
+    fn includes_hidden_lines() {
+        let info = FenceInfo::parse("rust playground-wrap:main").unwrap();
+        let wrapped = wrap(&Template::builtin(), &info, "# let a = 1;\nlet b = a;\n").unwrap();
+        assert_eq!(wrapped.code, "fn main() {let a = 1;\nlet b = a;\n}");
+    }
+
+    #[test]
+    fn unknown_templates_are_errors() {
+        let pass = PlaygroundButtonInserter::default();
+        let block = codeblock("rust tag:playground-button playground-wrap:nope", "");
+        let error = pass.process(&block).unwrap_err().to_string();
+        assert!(error.starts_with("Unknown playground template `nope`"));
     }
 }
@@ -0,0 +1,392 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::Mutex,
+    time::Duration,
+};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use wait_timeout::ChildExt;
+
+use crate::{
+    codeblock::Codeblock,
+    format::write_atomically,
+    pass::{CodeblockPass, ExtraBlock, PassResult},
+    processor::playground_button_inserter::{wrap, Template},
+    render::escape_html,
+};
+
+/// Settings for running code blocks marked with `run`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RunConfig {
+    /// Time limit for compiling and for running, each in seconds.
+    pub timeout: u64,
+
+    /// Directory for cached results, keyed by the hash of the code, how it is run and the
+    /// toolchain. Relative to the configuration file.
+    pub cache: PathBuf,
+
+    /// How output is inserted when the fence does not say: `text` or `html`.
+    pub output: OutputStyle,
+}
+
+impl Default for RunConfig {
+    fn default() -> Self {
+        Self {
+            timeout: 10,
+            cache: PathBuf::from(".codeblock-cache/run"),
+            output: OutputStyle::Text,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStyle {
+    /// A `text` code block after the code block.
+    Text,
+    /// A collapsible `<details>` section after the code block.
+    Html,
+}
+
+/// What is expected from compiling and running a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Expectation {
+    Success,
+    Panic,
+    CompileFailure,
+}
+
+/// The cached outcome of compiling and running a block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Outcome {
+    compiled: bool,
+    success: bool,
+    /// `None` if the process was killed by a signal.
+    #[serde(default)]
+    exit_code: Option<i32>,
+    stdout: String,
+    stderr: String,
+}
+
+/// Compiles and runs Rust code blocks marked with `run` and inserts their output.
+///
+/// `should_panic` and `compile_fail` invert what counts as success, `expect-output:"..."` checks stdout.
+/// The output goes into a `text` block, or a collapsible HTML section with `run:html`.
+#[derive(Debug)]
+pub struct RunOutputInserter {
+    templates: BTreeMap<String, Template>,
+    config: RunConfig,
+    /// Identity of the toolchain of each channel, see [`RunOutputInserter::toolchain`].
+    toolchains: Mutex<HashMap<String, String>>,
+}
+
+impl Default for RunOutputInserter {
+    fn default() -> Self {
+        Self {
+            templates: Template::builtin(),
+            config: RunConfig::default(),
+            toolchains: Mutex::default(),
+        }
+    }
+}
+
+impl RunOutputInserter {
+    /// Wrap code in these templates in addition to the builtin ones, see [`Template::with_builtin`].
+    pub fn with_templates(mut self, templates: BTreeMap<String, Template>) -> Self {
+        self.templates = Template::with_builtin(templates);
+        self
+    }
+
+    pub fn with_config(mut self, config: RunConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    fn run(&self, code: &str, edition: &str, channel: &str) -> anyhow::Result<Outcome> {
+        let timeout = Duration::from_secs(self.config.timeout);
+        let directory = tempfile::tempdir().context("Failed to create temporary directory")?;
+        let source = directory.path().join("main.rs");
+        std::fs::write(&source, code).context("Failed to write source file")?;
+
+        let mut rustc = rustc(channel);
+        rustc
+            .arg(format!("--edition={edition}"))
+            .args(["--color=never", "-o", "main", "main.rs"]);
+        let compile =
+            run_with_timeout(rustc, directory.path(), timeout).context("Failed to run rustc")?;
+        if !compile.success {
+            return Ok(Outcome {
+                compiled: false,
+                success: false,
+                exit_code: compile.exit_code,
+                stdout: String::new(),
+                stderr: compile.stderr,
+            });
+        }
+
+        let program = Command::new(directory.path().join("main"));
+        let run = run_with_timeout(program, directory.path(), timeout)
+            .context("Failed to run compiled program")?;
+        Ok(Outcome {
+            compiled: true,
+            ..run
+        })
+    }
+
+    /// Version and sysroot of the toolchain of `channel`, so that cached results of another
+    /// toolchain are not used.
+    fn toolchain(&self, channel: &str) -> anyhow::Result<String> {
+        let mut toolchains = self.toolchains.lock().expect("Poisoned toolchains");
+        if let Some(toolchain) = toolchains.get(channel) {
+            return Ok(toolchain.clone());
+        }
+        let mut toolchain = String::new();
+        for args in [&["-vV"][..], &["--print", "sysroot"]] {
+            let output = rustc(channel)
+                .args(args)
+                .output()
+                .context("Failed to run rustc")?;
+            if !output.status.success() {
+                bail!(
+                    "Failed to run rustc {}:\n{}",
+                    args.join(" "),
+                    String::from_utf8_lossy(&output.stderr)
+                );
+            }
+            toolchain.push_str(&String::from_utf8_lossy(&output.stdout));
+        }
+        toolchains.insert(channel.to_string(), toolchain.clone());
+        Ok(toolchain)
+    }
+
+    fn cached_run(&self, code: &str, edition: &str, channel: &str) -> anyhow::Result<Outcome> {
+        let toolchain = self.toolchain(channel)?;
+        let mut hasher = Sha256::new();
+        for part in [code, edition, channel, &toolchain] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        let key = format!("{:x}", hasher.finalize());
+        let path = self.config.cache.join(format!("{key}.json"));
+        if let Some(outcome) = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+        {
+            return Ok(outcome);
+        }
+        let outcome = self.run(code, edition, channel)?;
+        let json = serde_json::to_string(&outcome)?;
+        if let Err(e) = write_atomically(&self.config.cache, &path, &json) {
+            eprintln!("Failed to cache output in {}: {e}", path.display());
+        }
+        Ok(outcome)
+    }
+}
+
+/// rustc of the toolchain of `channel`.
+fn rustc(channel: &str) -> Command {
+    let mut rustc = Command::new("rustc");
+    if channel != "stable" {
+        rustc.arg(format!("+{channel}"));
+    }
+    rustc
+}
+
+/// Run the command in `directory`, killing it after `timeout`.
+fn run_with_timeout(
+    mut command: Command,
+    directory: &Path,
+    timeout: Duration,
+) -> anyhow::Result<Outcome> {
+    let stdout_path = directory.join("stdout");
+    let stderr_path = directory.join("stderr");
+    let mut child = command
+        .current_dir(directory)
+        .stdin(Stdio::null())
+        .stdout(File::create(&stdout_path)?)
+        .stderr(File::create(&stderr_path)?)
+        .spawn()?;
+    let Some(status) = child.wait_timeout(timeout)? else {
+        child.kill()?;
+        child.wait()?;
+        bail!("Timed out after {} seconds", timeout.as_secs());
+    };
+    Ok(Outcome {
+        compiled: false,
+        success: status.success(),
+        exit_code: status.code(),
+        stdout: String::from_utf8_lossy(&std::fs::read(stdout_path)?).into_owned(),
+        stderr: String::from_utf8_lossy(&std::fs::read(stderr_path)?).into_owned(),
+    })
+}
+
+impl Outcome {
+    /// Whether the program panicked, as rustdoc decides it for `should_panic`:
+    /// the exit code of a panic, or a panic message.
+    fn panicked(&self) -> bool {
+        self.exit_code == Some(101) || self.stderr.contains("panicked at")
+    }
+}
+
+/// Wrap code without a `main` function in one, like rustdoc does.
+fn with_main(code: String) -> String {
+    if code.contains("fn main") {
+        code
+    } else {
+        format!("fn main() {{\n{code}}}\n")
+    }
+}
+
+impl CodeblockPass for RunOutputInserter {
+    fn process(&self, codeblock: &Codeblock) -> anyhow::Result<PassResult> {
+        let info = &codeblock.info;
+        if !info.has("run") {
+            return Ok(PassResult::default());
+        }
+        if !matches!(info.language.as_deref(), Some("rust") | None) {
+            bail!("Only Rust code blocks can be run");
+        }
+
+        let expectation = match (info.has("should_panic"), info.has("compile_fail")) {
+            (false, false) => Expectation::Success,
+            (true, false) => Expectation::Panic,
+            (false, true) => Expectation::CompileFailure,
+            (true, true) => bail!("`should_panic` and `compile_fail` exclude each other"),
+        };
+
+        let wrapped = wrap(&self.templates, info, codeblock.program())?;
+        let code = with_main(wrapped.code);
+        let outcome = self.cached_run(&code, &wrapped.edition, &wrapped.channel)?;
+
+        match (expectation, outcome.compiled, outcome.success) {
+            (Expectation::Success, false, _) => {
+                bail!("Failed to compile:\n{}", outcome.stderr)
+            }
+            (Expectation::Success, true, false) => {
+                bail!("Program failed:\n{}{}", outcome.stdout, outcome.stderr)
+            }
+            (Expectation::Panic, false, _) => bail!("Failed to compile:\n{}", outcome.stderr),
+            (Expectation::Panic, true, true) => {
+                bail!("Expected a panic, but the program succeeded")
+            }
+            (Expectation::Panic, true, false) if !outcome.panicked() => {
+                bail!(
+                    "Expected a panic, but the program failed without one:\n{}{}",
+                    outcome.stdout,
+                    outcome.stderr
+                )
+            }
+            (Expectation::CompileFailure, true, _) => {
+                bail!("Expected a compile failure, but the code compiled")
+            }
+            _ => {}
+        }
+
+        if let Some(expected) = info.get("expect-output") {
+            let expected = expected.replace("\\n", "\n");
+            if outcome.stdout.trim_end() != expected.trim_end() {
+                bail!(
+                    "Unexpected output.\nExpected:\n{}\nActual:\n{}",
+                    expected.trim_end(),
+                    outcome.stdout.trim_end()
+                );
+            }
+        }
+
+        let output = format!("{}{}", outcome.stdout, outcome.stderr);
+        let style = match info.get("run") {
+            Some("html") => OutputStyle::Html,
+            Some("text") => OutputStyle::Text,
+            Some(other) => bail!("Unknown output style `run:{other}`, use `text` or `html`"),
+            None => self.config.output,
+        };
+        Ok(match style {
+            OutputStyle::Text => PassResult {
+                blocks: vec![ExtraBlock {
+                    fence: "text".into(),
+                    content: output,
+                }],
+                ..PassResult::default()
+            },
+            OutputStyle::Html => PassResult {
+                after: vec![format!(
+                    "<details>\n<summary>Output</summary>\n<pre><code>{}</code></pre>\n</details>\n\n",
+                    escape_html(&output)
+                )],
+                ..PassResult::default()
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn codeblock(fence: &str, content: &str) -> Codeblock {
+        Codeblock::new(Some(fence.into()), content.into(), 0..0, "")
+    }
+
+    fn inserter(cache: &Path) -> RunOutputInserter {
+        RunOutputInserter::default().with_config(RunConfig {
+            cache: cache.to_path_buf(),
+            ..RunConfig::default()
+        })
+    }
+
+    #[test]
+    fn inserts_output_and_caches_it() {
+        let cache = tempfile::tempdir().unwrap();
+        let pass = inserter(cache.path());
+        let block = codeblock(
+            r#"rust run expect-output:"Hello\n""#,
+            "println!(\"Hello\");\n",
+        );
+        let result = pass.process(&block).unwrap();
+        assert_eq!(
+            result.blocks,
+            [ExtraBlock {
+                fence: "text".into(),
+                content: "Hello\n".into()
+            }]
+        );
+        assert_eq!(std::fs::read_dir(cache.path()).unwrap().count(), 1);
+        assert_eq!(pass.process(&block).unwrap(), result);
+    }
+
+    #[test]
+    fn checks_expectations() {
+        let cache = tempfile::tempdir().unwrap();
+        let pass = inserter(cache.path());
+
+        let panics = codeblock("rust run:html should_panic", "panic!(\"<oops>\");\n");
+        let result = pass.process(&panics).unwrap();
+        assert!(result.after[0].contains("&lt;oops&gt;"));
+
+        let exits = codeblock("rust run should_panic", "std::process::exit(1);\n");
+        assert!(pass
+            .process(&exits)
+            .unwrap_err()
+            .to_string()
+            .starts_with("Expected a panic, but the program failed without one"));
+
+        let fails = codeblock("rust run compile_fail", "let a: u8 = \"no\";\n");
+        assert!(pass.process(&fails).is_ok());
+
+        let compiles = codeblock("rust run compile_fail", "let a: u8 = 1;\n");
+        assert!(pass.process(&compiles).is_err());
+
+        let wrong = codeblock("rust run expect-output:yes", "println!(\"no\");\n");
+        assert!(pass
+            .process(&wrong)
+            .unwrap_err()
+            .to_string()
+            .starts_with("Unexpected output."));
+    }
+}
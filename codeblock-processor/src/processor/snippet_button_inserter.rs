@@ -1,63 +1,681 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context};
 use itertools::Itertools;
-use pulldown_cmark::CowStr;
-use snippet_extractor::Snippets;
+use serde::{Deserialize, Serialize};
+use snippet_extractor::{
+    fence::FenceInfo,
+    language,
+    parser::parse,
+    paths::{self, relative_to},
+    Snippet, Snippets,
+};
+use urlencoding::encode;
+
+use crate::{
+    codeblock::Codeblock,
+    git::Repository,
+    pass::{Button, ButtonAction, CodeblockPass, PassResult},
+    slice::{collapse, elide_bodies, select_lines},
+};
+
+/// Settings for the editor and permalink buttons of `marker:` blocks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EditorConfig {
+    /// Names of the builtin or custom targets to add buttons for, in order.
+    pub targets: Vec<String>,
+
+    /// Git remote for the `github` and `gitlab` permalinks.
+    pub remote: String,
+
+    /// SSH host for `vscode-remote`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+
+    /// Custom targets by name, replacing builtin targets of the same name.
+    pub templates: BTreeMap<String, EditorTemplate>,
+}
+
+impl Default for EditorConfig {
+    fn default() -> Self {
+        Self {
+            targets: vec!["vscode".into()],
+            remote: "origin".into(),
+            host: None,
+            templates: BTreeMap::new(),
+        }
+    }
+}
+
+/// A link to the location of a snippet.
+///
+/// In `url`, `{path}` is replaced with the path of the snippet file, percent-encoded in the query
+/// string, `{line}` and `{column}`
+/// with the location of its marker and `{end_line}` with its last line.
+/// For permalinks, `{web_url}` is the web page of the git remote, `{commit}` the HEAD commit
+/// and `{repo_path}` the path in the repository. `{host}` is the configured SSH host.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EditorTemplate {
+    pub url: String,
+    pub label: String,
+}
+
+impl EditorTemplate {
+    fn new(url: &str, label: &str) -> Self {
+        Self {
+            url: url.into(),
+            label: label.into(),
+        }
+    }
+
+    /// The default target.
+    fn vscode() -> Self {
+        Self::new("vscode://file/{path}:{line}:{column}", "Open VSCode")
+    }
+
+    /// The builtin targets: `vscode`, `vscode-remote`, `jetbrains`, `zed`, `nvim`, `github` and `gitlab`.
+    pub fn builtin() -> BTreeMap<String, EditorTemplate> {
+        BTreeMap::from([
+            ("vscode".into(), Self::vscode()),
+            (
+                "vscode-remote".into(),
+                Self::new(
+                    "vscode://vscode-remote/ssh-remote+{host}{path}:{line}:{column}",
+                    "Open VSCode Remote",
+                ),
+            ),
+            (
+                "jetbrains".into(),
+                Self::new(
+                    "idea://open?file={path}&line={line}&column={column}",
+                    "Open JetBrains",
+                ),
+            ),
+            (
+                "zed".into(),
+                Self::new("zed://file{path}:{line}:{column}", "Open Zed"),
+            ),
+            (
+                "nvim".into(),
+                Self::new(
+                    "nvim://open?file={path}&line={line}&column={column}",
+                    "Open Neovim",
+                ),
+            ),
+            (
+                "github".into(),
+                Self::new(
+                    "{web_url}/blob/{commit}/{repo_path}#L{line}-L{end_line}",
+                    "GitHub",
+                ),
+            ),
+            (
+                "gitlab".into(),
+                Self::new(
+                    "{web_url}/-/blob/{commit}/{repo_path}#L{line}-{end_line}",
+                    "GitLab",
+                ),
+            ),
+        ])
+    }
+}
+
+/// Builds editor and permalink buttons for locations in source files.
+#[derive(Debug, Clone)]
+pub struct EditorLinks {
+    editors: Vec<(String, EditorTemplate)>,
+    host: Option<String>,
+    repository: Option<Repository>,
+}
+
+impl Default for EditorLinks {
+    fn default() -> Self {
+        Self {
+            editors: vec![("vscode".into(), EditorTemplate::vscode())],
+            host: None,
+            repository: None,
+        }
+    }
+}
 
-use super::ButtonInserter;
+impl EditorLinks {
+    /// Add buttons for the configured targets instead of only VSCode.
+    /// For permalinks, the git repository is looked up from `directory`.
+    pub fn with_editors(mut self, config: EditorConfig, directory: &Path) -> anyhow::Result<Self> {
+        let mut templates = EditorTemplate::builtin();
+        templates.extend(config.templates);
+        self.editors = config
+            .targets
+            .iter()
+            .map(|name| match templates.get(name) {
+                Some(template) => Ok((name.clone(), template.clone())),
+                None => Err(anyhow!(
+                    "Unknown editor target `{name}`, available are: {}",
+                    templates.keys().join(", ")
+                )),
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let uses = |placeholder: &str| {
+            self.editors
+                .iter()
+                .any(|(_, template)| template.url.contains(placeholder))
+        };
+        if uses("{host}") && config.host.is_none() {
+            bail!("The editor target needs `host` to be configured");
+        }
+        if uses("{web_url}") || uses("{commit}") || uses("{repo_path}") {
+            self.repository = Some(Repository::discover(directory, &config.remote)?);
+        }
+        self.host = config.host;
+        Ok(self)
+    }
+
+    /// One button per editor target for the location. `label` is appended to the button labels.
+    pub fn buttons(&self, location: &Location, label: Option<&str>) -> Vec<Button> {
+        self.editors
+            .iter()
+            .map(|(name, template)| Button {
+                name: format!("editor:{name}"),
+                action: ButtonAction::Open(self.url(template, location)),
+                label: match label {
+                    Some(label) => format!("{} ({label})", template.label),
+                    None => template.label.clone(),
+                },
+            })
+            .collect()
+    }
+
+    /// A JavaScript expression for the URL of the location, filled into the template.
+    fn url(&self, template: &EditorTemplate, location: &Location) -> String {
+        let path = &location.path;
+        let fill = |text: &str| {
+            let mut text = text
+                .replace("{line}", &location.line.to_string())
+                .replace("{column}", &location.column.to_string())
+                .replace("{end_line}", &location.end_line.to_string())
+                .replace("{host}", self.host.as_deref().unwrap_or_default());
+            if let Some(repository) = &self.repository {
+                let repo_path = relative_to(path, &repository.root);
+                let repo_path = repo_path.display().to_string().replace('\\', "/");
+                let commit = location.commit.as_ref().unwrap_or(&repository.commit);
+                text = text
+                    .replace("{web_url}", &repository.web_url)
+                    .replace("{commit}", commit)
+                    .replace("{repo_path}", &repo_path);
+            }
+            text.replace('\\', "\\\\").replace('\'', "\\'")
+        };
+        match template.url.split_once("{path}") {
+            Some((prefix, suffix)) => {
+                let path = path.display().to_string();
+                let path = if prefix.contains('?') {
+                    encode(&path).into_owned()
+                } else {
+                    path
+                };
+                format!("'{}{}{}'", fill(prefix), fill(&path), fill(suffix))
+            }
+            None => format!("'{}'", fill(&template.url)),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct SnippetButtonInserter {
     snippets: Snippets,
+    links: EditorLinks,
+    include_base: PathBuf,
 }
 
 impl SnippetButtonInserter {
     pub fn with_snippets(snippets: Snippets) -> Self {
-        Self { snippets }
+        Self {
+            snippets,
+            links: EditorLinks::default(),
+            include_base: PathBuf::from("."),
+        }
+    }
+
+    /// Add buttons for the configured targets instead of only VSCode.
+    /// For permalinks, the git repository is looked up from the snippet base directory.
+    pub fn with_editors(mut self, config: EditorConfig) -> anyhow::Result<Self> {
+        let directory = match &self.snippets.base {
+            Some(base) => base.clone(),
+            None => std::env::current_dir()?,
+        };
+        self.links = self.links.with_editors(config, &directory)?;
+        Ok(self)
+    }
+
+    /// Read the base directory for `include:` paths. By default, they are relative to the working directory.
+    #[must_use]
+    pub fn with_include_base(mut self, include_base: PathBuf) -> Self {
+        self.include_base = include_base;
+        self
+    }
+
+    /// The editor links, to share them with other passes.
+    pub fn editor_links(&self) -> &EditorLinks {
+        &self.links
     }
-}
 
-impl ButtonInserter for SnippetButtonInserter {
-    fn handle_codeblock(
+    /// Read the lines selected by an `include:` attribute, such as `src/main.rs#L10-L30`
+    /// or `src/main.rs#marker=setup`, and where they are.
+    fn include(&self, spec: &str) -> anyhow::Result<(String, Location)> {
+        let (file, fragment) = match spec.rsplit_once('#') {
+            Some((file, fragment)) => (file, Some(fragment)),
+            None => (spec, None),
+        };
+        let path = paths::absolute(&self.include_base.join(file))?;
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read included file {}", path.display()))?;
+        let line_count = text.lines().count();
+        let (start, end) = match fragment {
+            None => (1, line_count),
+            Some(fragment) => {
+                if let Some(marker) = fragment.strip_prefix("marker=") {
+                    let name = path.file_name().map(Path::new).unwrap_or(&path);
+                    let snippet = parse(&text, name)
+                        .remove(marker)
+                        .with_context(|| format!("No marker `{marker}` in {}", path.display()))?;
+                    let location = Location::of_snippet(path, &snippet);
+                    return Ok((snippet.content, location));
+                }
+                parse_line_range(fragment)?
+            }
+        };
+        if start == 0 || end < start || end > line_count {
+            bail!(
+                "Lines {start} to {end} are outside of {}, which has {line_count} lines",
+                path.display()
+            );
+        }
+        let content = text
+            .lines()
+            .skip(start - 1)
+            .take(end + 1 - start)
+            .join("\n");
+        let location = Location {
+            path,
+            line: start,
+            content_line: start,
+            end_line: end,
+            column: 0,
+            commit: None,
+        };
+        Ok((content, location))
+    }
+
+    /// Fill the code block with `content` from `location` and add the editor buttons.
+    ///
+    /// `lines:`, `collapse:` and `elide-bodies` select the part of the content which is shown.
+    fn fill_block(
         &self,
-        fence: &CowStr,
-        _code: &CowStr,
-        current_block: &mut Option<String>,
-        current_url: &mut Option<String>,
-        current_btn_text: &mut Option<String>,
-    ) {
-        let context = fence
-            .split_whitespace()
-            .filter(|s| s.starts_with("marker:"))
-            .collect::<Vec<_>>()
-            .pop();
-        let hide_other_markers = fence
-            .split_whitespace()
-            .any(|token| token == "hide_other_markers");
-
-        if let Some(marker) = context {
-            let marker = marker.split_once(':').unwrap().1;
-            for value in self.snippets.snippets_for_id(marker) {
-                let snippet = &value.content;
-                let snippet = if hide_other_markers {
-                    snippet
-                        .lines()
-                        .filter(|line| !line.trim().starts_with("// marker-"))
-                        .filter(|line| !line.trim().starts_with("# marker-"))
-                        .join("\n")
-                } else {
-                    snippet.clone()
-                };
-                let dedented = textwrap::dedent(&snippet);
-                *current_block = Some(dedented);
-
-                let url = format!(
-                    "'vscode://file/'.concat(make_path('{}:{}:{}'))",
-                    value.file,
-                    value.line + 1,
-                    value.col
-                );
-                *current_url = Some(url);
-                *current_btn_text = Some("Open VSCode".into());
+        codeblock: &Codeblock,
+        content: &str,
+        language: Option<&str>,
+        location: &Location,
+    ) -> anyhow::Result<PassResult> {
+        let info = &codeblock.info;
+        let mut content = content.to_string();
+        let mut location = location.clone();
+        // Whether each line of the content is the line of the source file after the previous one.
+        let mut maps_lines = true;
+        if let Some(spec) = info.get("lines") {
+            let (lines, start) = select_lines(&content, spec)?;
+            location.content_line += start - 1;
+            location.line = location.content_line;
+            location.end_line = location.content_line + lines.lines().count() - 1;
+            content = lines;
+        }
+        for name in info
+            .get("collapse")
+            .into_iter()
+            .flat_map(|names| names.split(','))
+        {
+            content = collapse(&content, name)?;
+            maps_lines = false;
+        }
+        if info.has("elide-bodies") {
+            let language = match info.language.as_deref() {
+                Some("auto") | None => language,
+                fence_language => fence_language,
+            };
+            if let Some(language) = language.filter(|&language| language != "rust") {
+                bail!("`elide-bodies` needs Rust code, not {language}");
             }
+            let elided = elide_bodies(&content)?;
+            maps_lines &= elided.lines().count() == content.lines().count();
+            content = elided;
+        }
+        let displayed = display_content(info, &content);
+        maps_lines &= displayed.lines().count() == content.lines().count();
+
+        let fence = language.and_then(|language| {
+            let fence = codeblock.fence.as_deref().unwrap_or_default();
+            fence_with_language(fence, info, language)
+        });
+        Ok(PassResult {
+            fence,
+            content: Some(displayed),
+            buttons: self.links.buttons(&location, None),
+            source_line: maps_lines.then_some(location.content_line),
+            ..PassResult::default()
+        })
+    }
+}
+
+/// Code read from a file as shown in a code block: dedented, and without marker comments if
+/// the block has `hide_other_markers`.
+pub fn display_content(info: &FenceInfo, content: &str) -> String {
+    let content = if info.has("hide_other_markers") {
+        content
+            .lines()
+            .filter(|line| !line.trim().starts_with("// marker-"))
+            .filter(|line| !line.trim().starts_with("# marker-"))
+            .join("\n")
+    } else {
+        content.to_string()
+    };
+    textwrap::dedent(&content)
+}
+
+/// Where the lines of a code block come from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub path: PathBuf,
+    /// 1-based line the editor links point to.
+    pub line: usize,
+    /// 1-based line of the first line of content.
+    pub content_line: usize,
+    /// 1-based last line.
+    pub end_line: usize,
+    pub column: usize,
+    /// Commit for permalinks, if not the checked out one.
+    pub commit: Option<String>,
+}
+
+impl Location {
+    /// The location of a snippet: its start marker, up to its last line.
+    pub fn of_snippet(path: PathBuf, snippet: &Snippet) -> Self {
+        Self {
+            path,
+            line: snippet.line + 1,
+            content_line: snippet.line + 2,
+            end_line: snippet.line + 1 + snippet.content.lines().count(),
+            column: snippet.col,
+            commit: None,
         }
     }
 }
+
+/// Parse `L10-L30` or `L10` into 1-based line numbers.
+fn parse_line_range(fragment: &str) -> anyhow::Result<(usize, usize)> {
+    let line = |line: &str| -> anyhow::Result<usize> {
+        line.strip_prefix('L')
+            .and_then(|line| line.parse().ok())
+            .with_context(|| format!("Invalid line `{line}`, expected for example `L10`"))
+    };
+    match fragment.split_once('-') {
+        Some((start, end)) => Ok((line(start)?, line(end)?)),
+        None => line(fragment).map(|line| (line, line)),
+    }
+}
+
+impl CodeblockPass for SnippetButtonInserter {
+    fn process(&self, codeblock: &Codeblock) -> anyhow::Result<PassResult> {
+        let info = &codeblock.info;
+        let mut result = PassResult::default();
+
+        if let Some(spec) = info.get("include") {
+            let (content, location) = self.include(spec)?;
+            let language = language::detect(&location.path, &content);
+            return self.fill_block(codeblock, &content, language.as_deref(), &location);
+        }
+
+        if let Some(marker) = info.get("marker") {
+            for snippet in self.snippets.snippets_for_id(marker) {
+                let location =
+                    Location::of_snippet(self.snippets.absolute_path(&snippet), &snippet);
+                result = self.fill_block(
+                    codeblock,
+                    &snippet.content,
+                    snippet.language.as_deref(),
+                    &location,
+                )?;
+            }
+        }
+        Ok(result)
+    }
+
+    fn provides_content(&self) -> bool {
+        true
+    }
+}
+
+/// Fill in the fence language if it is missing or set to `auto`.
+/// Returns `None` if the fence already names a language.
+pub(crate) fn fence_with_language(fence: &str, info: &FenceInfo, language: &str) -> Option<String> {
+    let fence = fence.trim_start();
+    match info.language.as_deref() {
+        None if fence.is_empty() => Some(language.to_string()),
+        None => Some(format!("{language} {fence}")),
+        Some("auto") => Some(fence.replacen("auto", language, 1)),
+        Some(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fill(fence: &str, language: &str) -> Option<String> {
+        fence_with_language(fence, &FenceInfo::parse(fence).unwrap(), language)
+    }
+
+    #[test]
+    fn fills_in_missing_or_auto_language() {
+        assert_eq!(fill("marker:foo", "rust"), Some("rust marker:foo".into()));
+        assert_eq!(
+            fill("auto marker:foo", "toml"),
+            Some("toml marker:foo".into())
+        );
+        assert_eq!(fill("", "rust"), Some("rust".into()));
+        assert_eq!(fill("bash marker:foo", "rust"), None);
+    }
+
+    fn inserter() -> SnippetButtonInserter {
+        let snippets = Snippets::from_json(
+            r#"{
+                "version": 3,
+                "base": "/home/me/project",
+                "snippets": {
+                    "src/main.rs": {
+                        "setup": { "content": "a\nb\n", "file": "src/main.rs", "line": 9, "col": 0 }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        SnippetButtonInserter::with_snippets(snippets)
+    }
+
+    fn urls(inserter: &SnippetButtonInserter) -> Vec<(String, String)> {
+        let codeblock = Codeblock::new(Some("rust marker:setup".into()), String::new(), 0..0, "");
+        inserter
+            .process(&codeblock)
+            .unwrap()
+            .buttons
+            .into_iter()
+            .map(|button| match button.action {
+                ButtonAction::Open(url) => (button.name, url),
+                ButtonAction::Copy => panic!("Editor buttons must open a URL"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fills_editor_templates() {
+        let mut inserter = inserter();
+        inserter.links.editors = ["vscode", "zed", "github"]
+            .into_iter()
+            .map(|name| (name.to_string(), EditorTemplate::builtin()[name].clone()))
+            .collect();
+        inserter.links.repository = Some(Repository {
+            root: "/home/me".into(),
+            web_url: "https://github.com/me/project".into(),
+            commit: "abc123".into(),
+        });
+        assert_eq!(
+            urls(&inserter),
+            [
+                (
+                    "editor:vscode".to_string(),
+                    "'vscode://file//home/me/project/src/main.rs:10:0'".to_string()
+                ),
+                (
+                    "editor:zed".to_string(),
+                    "'zed://file/home/me/project/src/main.rs:10:0'".to_string()
+                ),
+                (
+                    "editor:github".to_string(),
+                    "'https://github.com/me/project/blob/abc123/project/src/main.rs#L10-L12'"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn encodes_paths_in_query_strings() {
+        let snippets = Snippets::from_json(
+            r#"{
+                "version": 3,
+                "base": "/home/me/my project",
+                "snippets": {
+                    "src/a&b.rs": {
+                        "setup": { "content": "a\n", "file": "src/a&b.rs", "line": 0, "col": 0 }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        let mut inserter = SnippetButtonInserter::with_snippets(snippets);
+        inserter.links.editors = ["jetbrains", "zed"]
+            .into_iter()
+            .map(|name| (name.to_string(), EditorTemplate::builtin()[name].clone()))
+            .collect();
+        assert_eq!(
+            urls(&inserter),
+            [
+                (
+                    "editor:jetbrains".to_string(),
+                    "'idea://open?file=%2Fhome%2Fme%2Fmy%20project%2Fsrc%2Fa%26b.rs&line=1&column=0'"
+                        .to_string()
+                ),
+                (
+                    "editor:zed".to_string(),
+                    "'zed://file/home/me/my project/src/a&b.rs:1:0'".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn includes_files() {
+        let directory = tempfile::tempdir().unwrap();
+        std::fs::write(
+            directory.path().join("lib.rs"),
+            "fn a() {}\n    // marker-start:setup\n    let x = 1;\n    // marker-end:setup\nfn b() {}\n",
+        )
+        .unwrap();
+        let inserter = SnippetButtonInserter::with_snippets(Snippets::default())
+            .with_include_base(directory.path().to_path_buf());
+        let include = |fence: &str| {
+            let codeblock = Codeblock::new(Some(fence.into()), String::new(), 0..0, "");
+            inserter.process(&codeblock)
+        };
+
+        let result = include("include:lib.rs#L1-L2").unwrap();
+        assert_eq!(result.fence.as_deref(), Some("rust include:lib.rs#L1-L2"));
+        assert_eq!(
+            result.content.as_deref(),
+            Some("fn a() {}\n    // marker-start:setup")
+        );
+        let ButtonAction::Open(url) = &result.buttons[0].action else {
+            panic!("Editor buttons must open a URL");
+        };
+        assert!(url.ends_with("lib.rs:1:0'"));
+
+        let result = include("text include:lib.rs#marker=setup").unwrap();
+        assert_eq!(result.fence, None);
+        assert_eq!(result.content.as_deref(), Some("let x = 1;"));
+
+        assert!(include("include:lib.rs#L4-L9").is_err());
+        assert!(include("include:lib.rs#marker=nope").is_err());
+        assert!(include("include:missing.rs")
+            .unwrap_err()
+            .to_string()
+            .starts_with("Failed to read included file"));
+    }
+
+    #[test]
+    fn slices_and_collapses_snippets() {
+        let directory = tempfile::tempdir().unwrap();
+        std::fs::write(
+            directory.path().join("lib.rs"),
+            "// marker-start:outer\nfn a() {\n    // marker-start:inner\n    let x = 1;\n    // marker-end:inner\n    x\n}\n\nfn b() {\n    a();\n}\n// marker-end:outer\n",
+        )
+        .unwrap();
+        let inserter = SnippetButtonInserter::with_snippets(Snippets::default())
+            .with_include_base(directory.path().to_path_buf());
+        let include = |attributes: &str| {
+            let fence = format!("rust include:lib.rs#marker=outer {attributes}");
+            let codeblock = Codeblock::new(Some(fence), String::new(), 0..0, "");
+            inserter.process(&codeblock)
+        };
+
+        let result = include("lines:5-7").unwrap();
+        assert_eq!(result.content.as_deref(), Some("    x\n}\n"));
+        assert_eq!(result.source_line, Some(6));
+        let ButtonAction::Open(url) = &result.buttons[0].action else {
+            panic!("Editor buttons must open a URL");
+        };
+        assert!(url.ends_with("lib.rs:6:0'"), "{url}");
+
+        let result = include("collapse:inner").unwrap();
+        assert_eq!(
+            result.content.as_deref(),
+            Some("fn a() {\n    { ... }\n    x\n}\n\nfn b() {\n    a();\n}")
+        );
+        assert_eq!(result.source_line, None);
+        let result = include("elide-bodies").unwrap();
+        assert_eq!(
+            result.content.as_deref(),
+            Some("fn a() { .. }\n\nfn b() { .. }")
+        );
+        assert_eq!(result.source_line, None);
+        assert_eq!(include("hide_other_markers").unwrap().source_line, None);
+        assert_eq!(
+            include("lines:5-7 hide_other_markers").unwrap().source_line,
+            Some(6)
+        );
+
+        assert!(include("lines:3-30").is_err());
+        assert!(include("collapse:nope").is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_editors() {
+        let config = EditorConfig {
+            targets: vec!["emacs".into()],
+            ..EditorConfig::default()
+        };
+        assert!(inserter().with_editors(config).is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use std::sync::Arc;
+
+use crate::{
+    codeblock::Codeblock,
+    highlight::Highlighter,
+    pass::{CodeblockPass, PassResult},
+    processor::line_annotator::{annotates, LineAnnotator},
+};
+
+/// Replaces code blocks with HTML with their syntax highlighted, for exports without client-side
+/// highlighting.
+///
+/// Must run after [`HiddenLineRemover`], and before [`LineAnnotator`], which renders highlighted and
+/// numbered lines and callouts with the same highlighter, so those blocks are left to it.
+/// Blocks already rendered by a previous pass, such as diagrams and diffs, are kept.
+///
+/// [`HiddenLineRemover`]: crate::processor::hidden_line_remover::HiddenLineRemover
+#[derive(Debug)]
+pub struct SyntaxHighlighter {
+    highlighter: Arc<Highlighter>,
+    lines: LineAnnotator,
+}
+
+impl SyntaxHighlighter {
+    pub fn new(highlighter: Arc<Highlighter>) -> Self {
+        Self {
+            lines: LineAnnotator::default().with_highlighter(highlighter.clone()),
+            highlighter,
+        }
+    }
+
+    /// Emit the `line` CSS class on each line, like [`LineAnnotator::with_css_classes`].
+    pub fn with_css_classes(mut self, css_classes: bool) -> Self {
+        self.lines = self.lines.with_css_classes(css_classes);
+        self
+    }
+}
+
+impl CodeblockPass for SyntaxHighlighter {
+    fn process(&self, codeblock: &Codeblock) -> anyhow::Result<PassResult> {
+        let known = codeblock
+            .info
+            .language
+            .as_deref()
+            .is_some_and(|language| self.highlighter.knows(language));
+        if codeblock.rendered || !known || annotates(codeblock) {
+            return Ok(PassResult::default());
+        }
+        Ok(PassResult {
+            html: Some(self.lines.plain_html(codeblock)?),
+            ..PassResult::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::highlight::HighlightConfig;
+
+    use super::*;
+
+    #[test]
+    fn highlights_together_with_line_annotations() {
+        let highlighter = Arc::new(Highlighter::new(&HighlightConfig::default(), true).unwrap());
+        let codeblock =
+            |fence: &str| Codeblock::new(Some(fence.into()), "let a = 1;\n".into(), 0..0, "");
+
+        let pass = SyntaxHighlighter::new(highlighter.clone());
+        assert_eq!(
+            pass.process(&codeblock("rust")).unwrap().html.unwrap(),
+            "<pre><code class=\"language-rust\"><span><span class=\"hljs-keyword\">let</span> a <span class=\"hljs-operator\">=</span> <span class=\"hljs-number\">1</span>;</span>\n</code></pre>\n"
+        );
+        assert_eq!(
+            pass.process(&codeblock("unknown")).unwrap(),
+            PassResult::default()
+        );
+        assert_eq!(
+            pass.process(&codeblock("rust linenos")).unwrap(),
+            PassResult::default()
+        );
+
+        let annotator = LineAnnotator::default()
+            .with_style(crate::processor::line_annotator::LineStyle::Fence)
+            .with_css_classes(true)
+            .with_highlighter(highlighter);
+        let html = annotator
+            .process(&codeblock("rust highlight:1"))
+            .unwrap()
+            .html
+            .unwrap();
+        assert!(html.contains(
+            "<span class=\"line line-highlight\"><span class=\"hljs-keyword\">let</span>"
+        ));
+    }
+}
@@ -0,0 +1,402 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use base64::{engine::general_purpose::URL_SAFE, Engine};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use snippet_extractor::fence::FenceInfo;
+use urlencoding::encode;
+
+use crate::{
+    codeblock::Codeblock,
+    format::Formatter,
+    pass::{Button, ButtonAction, CodeblockPass, PassResult},
+    processor::playground_button_inserter::{wrap, Template},
+};
+
+/// Compiler Explorer settings for one language.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Godbolt {
+    /// Compiler id as used by godbolt.org, such as `r1750` for rustc 1.75.0 or `g132` for GCC 13.2.
+    pub compiler: String,
+
+    /// Compiler flags.
+    #[serde(default)]
+    pub flags: String,
+}
+
+impl Godbolt {
+    fn new(compiler: &str, flags: &str) -> Self {
+        Self {
+            compiler: compiler.into(),
+            flags: flags.into(),
+        }
+    }
+
+    /// The builtin settings by fence language: `rust`, `cpp` and `c`.
+    pub fn builtin() -> BTreeMap<String, Godbolt> {
+        BTreeMap::from([
+            ("rust".into(), Self::new("nightly", "-C opt-level=3")),
+            ("cpp".into(), Self::new("g132", "-O2")),
+            ("c".into(), Self::new("cg132", "-O2")),
+        ])
+    }
+
+    /// The builtin settings, replaced by the given ones for their languages.
+    pub fn with_builtin(godbolt: BTreeMap<String, Godbolt>) -> BTreeMap<String, Godbolt> {
+        let mut all = Self::builtin();
+        all.extend(godbolt);
+        all
+    }
+}
+
+/// A playground given by a URL template.
+///
+/// `{code}` is replaced with the percent-encoded code, `{code_base64}` with the code in URL-safe base64,
+/// and `{language}` with the language of the code block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UrlTarget {
+    pub url: String,
+
+    /// Text on the button, the name of the target by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+impl UrlTarget {
+    pub fn url(&self, code: &str, language: &str) -> String {
+        self.url
+            .replace("{code}", &encode(code))
+            .replace("{code_base64}", &URL_SAFE.encode(code))
+            .replace("{language}", &encode(language))
+    }
+}
+
+/// Adds buttons opening the code in other playgrounds, selected with `playground:godbolt,go,typescript`.
+///
+/// The buttons are named `playground:<target>`, so `buttons:playground` selects all of them.
+#[derive(Debug)]
+pub struct TargetButtonInserter {
+    templates: BTreeMap<String, Template>,
+    godbolt: BTreeMap<String, Godbolt>,
+    targets: BTreeMap<String, UrlTarget>,
+    formatter: Arc<Formatter>,
+}
+
+impl Default for TargetButtonInserter {
+    fn default() -> Self {
+        Self {
+            templates: Template::builtin(),
+            godbolt: Godbolt::builtin(),
+            targets: BTreeMap::new(),
+            formatter: Arc::default(),
+        }
+    }
+}
+
+impl TargetButtonInserter {
+    /// Wrap code in these templates in addition to the builtin ones, see [`Template::with_builtin`].
+    pub fn with_templates(mut self, templates: BTreeMap<String, Template>) -> Self {
+        self.templates = Template::with_builtin(templates);
+        self
+    }
+
+    /// Use these Compiler Explorer settings by language, replacing the builtin ones of the same language.
+    pub fn with_godbolt(mut self, godbolt: BTreeMap<String, Godbolt>) -> Self {
+        self.godbolt = Godbolt::with_builtin(godbolt);
+        self
+    }
+
+    /// Add targets given by URL templates. They replace builtin targets of the same name.
+    pub fn with_targets(mut self, targets: BTreeMap<String, UrlTarget>) -> Self {
+        self.targets = targets;
+        self
+    }
+
+    /// Format Rust code with this formatter, shared with other passes.
+    pub fn with_formatter(mut self, formatter: Arc<Formatter>) -> Self {
+        self.formatter = formatter;
+        self
+    }
+
+    fn button(
+        &self,
+        target: &str,
+        code: &str,
+        info: &FenceInfo,
+        edition: &str,
+    ) -> anyhow::Result<Button> {
+        let language = info.language.as_deref().unwrap_or("rust");
+        let (label, action) = if let Some(url_target) = self.targets.get(target) {
+            let label = url_target
+                .label
+                .clone()
+                .unwrap_or_else(|| target.to_string());
+            let url = url_target
+                .url(code, language)
+                .replace('\\', "\\\\")
+                .replace('\'', "\\'");
+            (label, ButtonAction::Open(format!("'{url}'")))
+        } else {
+            match target {
+                "godbolt" => (
+                    "Assembly".into(),
+                    ButtonAction::Open(self.godbolt_url(code, info, edition)?),
+                ),
+                "go" => ("Go Playground".into(), ButtonAction::Open(go_url(code))),
+                "typescript" => (
+                    "TS Playground".into(),
+                    ButtonAction::Open(typescript_url(code)),
+                ),
+                _ => anyhow::bail!(
+                    "Unknown playground target `{target}`, available are: {}",
+                    ["godbolt", "go", "typescript"]
+                        .into_iter()
+                        .chain(self.targets.keys().map(String::as_str))
+                        .unique()
+                        .join(", ")
+                ),
+            }
+        };
+        Ok(Button {
+            name: format!("playground:{target}"),
+            action,
+            label,
+        })
+    }
+
+    /// The compiler and flags come from the settings for the language,
+    /// overridden by `godbolt-compiler:` and `godbolt-flags:`.
+    fn godbolt_url(&self, code: &str, info: &FenceInfo, edition: &str) -> anyhow::Result<String> {
+        let language = info.language.as_deref().unwrap_or("rust");
+        let configured = self.godbolt.get(language);
+        let Some(compiler) = info
+            .get("godbolt-compiler")
+            .or(configured.map(|godbolt| godbolt.compiler.as_str()))
+        else {
+            anyhow::bail!(
+                "No Compiler Explorer compiler for `{language}`, add `godbolt-compiler:` or configure `[godbolt.{language}]`"
+            );
+        };
+        let mut flags = info
+            .get("godbolt-flags")
+            .or(configured.map(|godbolt| godbolt.flags.as_str()))
+            .unwrap_or_default()
+            .to_string();
+        let language = match language {
+            "cpp" => "c++",
+            language => language,
+        };
+        if language == "rust" && !flags.contains("--edition") {
+            flags = format!("--edition {edition} {flags}");
+        }
+        let state = json!({
+            "sessions": [{
+                "id": 1,
+                "language": language,
+                "source": code,
+                "compilers": [{ "id": compiler, "options": flags }],
+            }]
+        });
+        Ok(format!(
+            "'https://godbolt.org/clientstate/{}'",
+            URL_SAFE.encode(state.to_string())
+        ))
+    }
+}
+
+/// The Go playground does not accept code in the URL, so the code is copied to the clipboard instead.
+fn go_url(code: &str) -> String {
+    format!(
+        "(navigator.clipboard.writeText(decodeURIComponent('{}')), 'https://go.dev/play/')",
+        encode(code)
+    )
+}
+
+fn typescript_url(code: &str) -> String {
+    format!(
+        "'https://www.typescriptlang.org/play?#src={}'",
+        encode(code)
+    )
+}
+
+impl CodeblockPass for TargetButtonInserter {
+    fn process(&self, codeblock: &Codeblock) -> anyhow::Result<PassResult> {
+        let info = &codeblock.info;
+        let targets = info
+            .values("playground")
+            .flat_map(|targets| targets.split(','))
+            .map(str::trim)
+            .filter(|target| !target.is_empty())
+            .unique()
+            .collect::<Vec<_>>();
+        if targets.is_empty() {
+            return Ok(PassResult::default());
+        }
+
+        let language = info.language.as_deref().unwrap_or("rust");
+        let wrapped = wrap(&self.templates, info, codeblock.program())?;
+        let code = if language == "rust" {
+            self.formatter.format(&wrapped.code, &wrapped.edition)
+        } else {
+            wrapped.code
+        };
+
+        let buttons = targets
+            .into_iter()
+            .map(|target| self.button(target, &code, info, &wrapped.edition))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(PassResult {
+            buttons,
+            ..PassResult::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn codeblock(fence: &str, content: &str) -> Codeblock {
+        Codeblock::new(Some(fence.into()), content.into(), 0..0, "")
+    }
+
+    fn urls(pass: &TargetButtonInserter, fence: &str, content: &str) -> Vec<(String, String)> {
+        pass.process(&codeblock(fence, content))
+            .unwrap()
+            .buttons
+            .into_iter()
+            .map(|button| match button.action {
+                ButtonAction::Open(url) => (button.name, url),
+                ButtonAction::Copy => panic!("Playground buttons must open a URL"),
+            })
+            .collect()
+    }
+
+    fn godbolt_session(
+        pass: &TargetButtonInserter,
+        fence: &str,
+        content: &str,
+    ) -> serde_json::Value {
+        let urls = urls(pass, fence, content);
+        assert_eq!(urls.len(), 1);
+        assert_eq!(urls[0].0, "playground:godbolt");
+
+        let state = urls[0]
+            .1
+            .strip_prefix("'https://godbolt.org/clientstate/")
+            .unwrap()
+            .strip_suffix('\'')
+            .unwrap();
+        let state: serde_json::Value =
+            serde_json::from_slice(&URL_SAFE.decode(state).unwrap()).unwrap();
+        state["sessions"][0].clone()
+    }
+
+    #[test]
+    fn encodes_godbolt_client_state() {
+        let pass = TargetButtonInserter::default().with_godbolt(BTreeMap::from([(
+            "rust".to_string(),
+            Godbolt::new("r1750", "-C opt-level=3"),
+        )]));
+        let session = godbolt_session(&pass, "rust playground:godbolt", "pub fn f() {}\n");
+        assert_eq!(session["language"], "rust");
+        assert_eq!(session["source"], "pub fn f() {}\n");
+        assert_eq!(session["compilers"][0]["id"], "r1750");
+        assert_eq!(
+            session["compilers"][0]["options"],
+            "--edition 2021 -C opt-level=3"
+        );
+    }
+
+    #[test]
+    fn picks_godbolt_compilers_by_language_and_fence() {
+        let pass = TargetButtonInserter::default();
+        let session = godbolt_session(&pass, "cpp playground:godbolt", "int f() {}\n");
+        assert_eq!(session["language"], "c++");
+        assert_eq!(session["compilers"][0]["id"], "g132");
+        assert_eq!(session["compilers"][0]["options"], "-O2");
+
+        let session = godbolt_session(
+            &pass,
+            r#"cpp playground:godbolt godbolt-compiler:clang1701 godbolt-flags:"-O3 -std=c++20""#,
+            "int f() {}\n",
+        );
+        assert_eq!(session["compilers"][0]["id"], "clang1701");
+        assert_eq!(session["compilers"][0]["options"], "-O3 -std=c++20");
+
+        let session = godbolt_session(
+            &pass,
+            "rust playground:godbolt godbolt-flags:-Copt-level=0",
+            "pub fn f() {}\n",
+        );
+        assert_eq!(session["compilers"][0]["id"], "nightly");
+        assert_eq!(
+            session["compilers"][0]["options"],
+            "--edition 2021 -Copt-level=0"
+        );
+
+        let error = pass
+            .process(&codeblock("zig playground:godbolt", ""))
+            .unwrap_err();
+        assert!(error
+            .root_cause()
+            .to_string()
+            .starts_with("No Compiler Explorer compiler for `zig`"));
+    }
+
+    #[test]
+    fn makes_go_and_typescript_buttons() {
+        let pass = TargetButtonInserter::default();
+        let urls = urls(
+            &pass,
+            "typescript playground:typescript,go",
+            "let a = 'b';\n",
+        );
+        assert_eq!(
+            urls,
+            [
+                (
+                    "playground:typescript".to_string(),
+                    "'https://www.typescriptlang.org/play?#src=let%20a%20%3D%20%27b%27%3B%0A'"
+                        .to_string()
+                ),
+                (
+                    "playground:go".to_string(),
+                    "(navigator.clipboard.writeText(decodeURIComponent('let%20a%20%3D%20%27b%27%3B%0A')), 'https://go.dev/play/')"
+                        .to_string()
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn fills_url_templates() {
+        let pass = TargetButtonInserter::default().with_targets(BTreeMap::from([(
+            "example".to_string(),
+            UrlTarget {
+                url: "https://example.com/{language}?code={code}&b64={code_base64}".into(),
+                label: None,
+            },
+        )]));
+        let urls = urls(&pass, "python playground:example", "print('hi')\n");
+        assert_eq!(
+            urls[0].1,
+            r"'https://example.com/python?code=print%28%27hi%27%29%0A&b64=cHJpbnQoJ2hpJykK'"
+        );
+    }
+
+    #[test]
+    fn unknown_targets_are_errors() {
+        let pass = TargetButtonInserter::default();
+        let error = pass
+            .process(&codeblock("rust playground:nope", ""))
+            .unwrap_err();
+        assert_eq!(
+            error.root_cause().to_string(),
+            "Unknown playground target `nope`, available are: godbolt, go, typescript"
+        );
+    }
+}
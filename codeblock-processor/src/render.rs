@@ -0,0 +1,230 @@
+use pulldown_cmark::{Event, Tag, TagEnd};
+use pulldown_cmark_to_cmark::cmark_with_options;
+
+use crate::{
+    codeblock::Codeblock,
+    pass::{Button, ButtonAction},
+    sentinel,
+    theme::{fill, Theme},
+};
+
+/// The values for the placeholders of the wrapper templates.
+pub fn block_values(codeblock: &Codeblock) -> [(&'static str, String); 2] {
+    [
+        ("block_id", format!("codeblock-{}", codeblock.line)),
+        (
+            "language",
+            codeblock.info.language.clone().unwrap_or_default(),
+        ),
+    ]
+}
+
+fn as_refs<'a>(values: &'a [(&'static str, String)]) -> Vec<(&'static str, &'a str)> {
+    values
+        .iter()
+        .map(|(key, value)| (*key, value.as_str()))
+        .collect()
+}
+
+/// Render the opening or closing wrapper template for a code block.
+pub fn wrapper<'a>(template: &str, codeblock: &Codeblock) -> Event<'a> {
+    let values = block_values(codeblock);
+    Event::Html(fill(template, &as_refs(&values)).into())
+}
+
+/// Render buttons in the top right corner of the enclosing code block wrapper.
+pub fn make_buttons<'a>(
+    theme: &Theme,
+    buttons: &[Button],
+    codeblock: &Codeblock,
+) -> Vec<Event<'a>> {
+    let values = block_values(codeblock);
+    let mut events = Vec::new();
+    events.push(Event::Html(
+        fill(&theme.buttons_start, &as_refs(&values)).into(),
+    ));
+    for button in buttons {
+        events.push(make_button(theme, button, &values));
+    }
+    events.push(Event::Html(
+        fill(&theme.buttons_end, &as_refs(&values)).into(),
+    ));
+    events
+}
+
+fn make_button<'a>(theme: &Theme, button: &Button, block: &[(&'static str, String)]) -> Event<'a> {
+    let (url, onclick) = match &button.action {
+        ButtonAction::Open(url) => (url.clone(), format!("window.open({url},'_blank')")),
+        ButtonAction::Copy => (
+            String::new(),
+            "navigator.clipboard.writeText((pre => pre.dataset.code ?? pre.innerText)(this.closest('div').querySelector('pre')))"
+                .to_string(),
+        ),
+    };
+    let name = button.name.replace(':', "-");
+    // The values end up in double-quoted attributes, like `onclick="{onclick}"`.
+    let label = escape_attribute(&button.label);
+    let url = escape_attribute(&url);
+    let onclick = escape_attribute(&onclick);
+    let mut values = as_refs(block);
+    values.extend([
+        ("name", name.as_str()),
+        ("label", label.as_str()),
+        ("icon", theme.icon(&button.name)),
+        ("url", url.as_str()),
+        ("onclick", onclick.as_str()),
+    ]);
+    Event::Html(fill(&theme.button, &values).into())
+}
+
+/// Escape text for use in HTML.
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Escape text for use in a double-quoted HTML attribute, keeping it on one line.
+pub fn escape_attribute(text: &str) -> String {
+    escape_html(text)
+        .replace('"', "&quot;")
+        .replace('\n', "&#10;")
+}
+
+/// Scripts required by the generated buttons, to be appended to the document.
+pub fn scripts<'a>(theme: &Theme) -> Vec<Event<'a>> {
+    let mut events = vec![Event::Text("\n".into())];
+    events.extend(html_block([Event::Html(sentinel::scripts().into())]));
+    for script in &theme.scripts {
+        events.extend(html_block([Event::Html(script.clone().into())]));
+        events.push(Event::Text("\n".into()));
+    }
+    events.extend(html_block([Event::Html(sentinel::end().into())]));
+    events
+}
+
+/// Generated HTML events as a block of its own, so that it starts on a new line instead of
+/// continuing a paragraph or heading.
+pub fn html_block<'a>(html: impl IntoIterator<Item = Event<'a>>) -> Vec<Event<'a>> {
+    let mut events = vec![Event::Start(Tag::HtmlBlock)];
+    events.extend(html);
+    events.push(Event::End(TagEnd::HtmlBlock));
+    events
+}
+
+/// Write events as markdown.
+pub fn markdown<'a>(events: impl IntoIterator<Item = Event<'a>>) -> anyhow::Result<String> {
+    let mut output = String::new();
+    let options = pulldown_cmark_to_cmark::Options {
+        list_token: '-',
+        ..Default::default()
+    };
+    cmark_with_options(events.into_iter(), &mut output, options)?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod test {
+    use pulldown_cmark_to_cmark::cmark;
+
+    use super::*;
+
+    fn codeblock() -> Codeblock {
+        Codeblock::new(Some("rust".into()), String::new(), 0..0, "")
+    }
+
+    #[test]
+    fn makes_button() {
+        let mut output = String::new();
+        let button = Button {
+            name: "example".to_string(),
+            action: ButtonAction::Open("'https://www.example.com'".to_string()),
+            label: "Example.com!".to_string(),
+        };
+        let buttons = make_buttons(&Theme::default(), &[button], &codeblock());
+        let _state = cmark(buttons.into_iter(), &mut output).unwrap();
+
+        let expected = r#"<p style="position: absolute; right: 10px; top: 10px; padding: 0; margin: 0; line-height: 0">
+<button
+    onclick="window.open('https://www.example.com','_blank')"
+    style="
+    height: fit-content;
+    margin: 0;
+    font-weight: bold;"
+>Example.com!
+</button>
+</p>
+"#;
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn escapes_button_attributes() {
+        let button = Button {
+            name: "example".to_string(),
+            action: ButtonAction::Open("'https://example.com/?a=1&b=\"2\"'".to_string()),
+            label: "\"Quoted\" <label>".to_string(),
+        };
+        let theme = Theme::preset(crate::theme::Preset::Mdbook, false);
+        let Event::Html(html) = make_button(&theme, &button, &block_values(&codeblock())) else {
+            panic!("Buttons must be HTML");
+        };
+        assert!(
+            html.contains(r#"title="&quot;Quoted&quot; &lt;label&gt;""#),
+            "{html}"
+        );
+        assert!(
+            html.contains(
+                r#"onclick="window.open('https://example.com/?a=1&amp;b=&quot;2&quot;','_blank')""#
+            ),
+            "{html}"
+        );
+    }
+
+    #[test]
+    fn makes_buttons_side_by_side() {
+        let mut output = String::new();
+        let buttons = [
+            Button {
+                name: "playground".to_string(),
+                action: ButtonAction::Open("'https://play.rust-lang.org'".to_string()),
+                label: "Playground".to_string(),
+            },
+            Button {
+                name: "copy".to_string(),
+                action: ButtonAction::Copy,
+                label: "Copy".to_string(),
+            },
+        ];
+        let _state = cmark(
+            make_buttons(&Theme::default(), &buttons, &codeblock()).into_iter(),
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(output.matches("<p ").count(), 1);
+        assert_eq!(output.matches("<button").count(), 2);
+        assert!(output.find(">Playground").unwrap() < output.find(">Copy").unwrap());
+        assert!(output.contains("navigator.clipboard.writeText("));
+    }
+
+    #[test]
+    fn emits_css_classes() {
+        let mut output = String::new();
+        let theme = Theme::preset(crate::theme::Preset::Plain, true);
+        let button = Button {
+            name: "playground:godbolt".to_string(),
+            action: ButtonAction::Open("'https://godbolt.org'".to_string()),
+            label: "Assembly".to_string(),
+        };
+        let _state = cmark(
+            make_buttons(&theme, &[button], &codeblock()).into_iter(),
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "<p class=\"codeblock-buttons\">\n<button type=\"button\" class=\"codeblock-button codeblock-button-playground-godbolt\" onclick=\"window.open('https://godbolt.org','_blank')\">Assembly</button>\n</p>\n"
+        );
+        assert!(!output.contains("style="));
+    }
+}
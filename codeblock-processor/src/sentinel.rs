@@ -0,0 +1,255 @@
+//! Sentinel comments around generated content, so that processed documents can be processed again
+//! or turned back into their source.
+//!
+//! The output for each code block is enclosed in `<!-- codeblock-processor:start ... -->` and
+//! `<!-- codeblock-processor:end -->`. The start comment carries the original code block, encoded
+//! so that it cannot end the comment. The scripts are enclosed in
+//! `<!-- codeblock-processor:scripts -->` and the same end comment.
+//!
+//! Everything outside the sentinels is the source document byte for byte, so putting back the
+//! original code blocks gives the source again.
+
+use std::ops::Range;
+
+use anyhow::{bail, Context};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+
+use crate::codeblock::Codeblock;
+
+const START: &str = "<!-- codeblock-processor:start ";
+const END: &str = "<!-- codeblock-processor:end -->";
+const SCRIPTS: &str = "<!-- codeblock-processor:scripts -->";
+
+/// A code block as written in the source.
+#[derive(Debug, Serialize, Deserialize)]
+struct Original {
+    /// The markdown of the code block, from its opening fence or indentation.
+    source: String,
+}
+
+/// The comment opening the output of a code block, carrying the markdown of the code block.
+pub fn start(source: &str) -> String {
+    let original = Original {
+        source: source.to_string(),
+    };
+    let json = serde_json::to_string(&original).expect("Code blocks must serialize");
+    format!("{START}{} -->\n", STANDARD.encode(json))
+}
+
+/// The comment closing generated content.
+pub fn end() -> String {
+    format!("{END}\n")
+}
+
+/// The comment opening the scripts.
+pub fn scripts() -> String {
+    format!("{SCRIPTS}\n")
+}
+
+/// Whether the document contains generated content.
+pub fn contains(input: &str) -> bool {
+    input.contains(START) || input.contains(SCRIPTS)
+}
+
+/// Generated content in a processed document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    /// Byte range of the generated content, from the start comment to the end comment.
+    /// The blank line before the scripts belongs to them.
+    pub range: Range<usize>,
+    /// The markdown of the code block the content was generated for, `None` for the scripts.
+    pub original: Option<String>,
+}
+
+/// The generated content of `input`, in document order.
+///
+/// Sentinel comments are found line by line, after the markers of the lists and block quotes
+/// they are in. Parsing the markdown would not find them reliably, as the generated HTML does
+/// not necessarily end where the following markdown starts.
+pub fn regions(input: &str) -> anyhow::Result<Vec<Region>> {
+    if !contains(input) {
+        return Ok(Vec::new());
+    }
+    let mut regions = Vec::new();
+    let mut open: Option<(usize, Option<String>)> = None;
+    let mut position = 0;
+    for line in input.split_inclusive('\n') {
+        let line_start = position;
+        position += line.len();
+        let sentinel =
+            line.trim_start_matches(|c: char| " \t>-*+.)".contains(c) || c.is_ascii_digit());
+        let start = line_start + line.len() - sentinel.len();
+        let sentinel = sentinel.trim_end();
+        let original = if let Some(encoded) = sentinel
+            .strip_prefix(START)
+            .and_then(|sentinel| sentinel.strip_suffix("-->"))
+        {
+            Some(Some(decode(encoded.trim())?))
+        } else if sentinel == SCRIPTS {
+            Some(None)
+        } else {
+            None
+        };
+        match (&open, original) {
+            (None, Some(original)) => open = Some((start, original)),
+            (Some(_), Some(_)) => {
+                bail!("Generated content is not closed with `{END}` before the next");
+            }
+            (Some(_), None) if sentinel == END => {
+                let (start, original) = open.take().expect("Generated content must be open");
+                regions.push(region(input, start..position, original));
+            }
+            _ => {}
+        }
+    }
+    if open.is_some() {
+        bail!("Generated content is not closed with `{END}`");
+    }
+    Ok(regions)
+}
+
+/// The region of generated content spanning `range`, which ends after the end comment's line.
+fn region(input: &str, mut range: Range<usize>, original: Option<String>) -> Region {
+    match &original {
+        // Fenced code blocks end at their closing fence, indented ones after their last line.
+        Some(source) if !source.ends_with('\n') && input[range.clone()].ends_with('\n') => {
+            range.end -= 1;
+        }
+        Some(_) => {}
+        None => {
+            if input[..range.start].ends_with("\n\n") {
+                range.start -= 1;
+            }
+        }
+    }
+    Region { range, original }
+}
+
+/// `input` with all generated content removed and the original code blocks put back.
+pub fn strip(input: &str) -> anyhow::Result<String> {
+    Ok(restore(input, &regions(input)?))
+}
+
+/// `input` with `regions` replaced by their original code blocks.
+pub fn restore(input: &str, regions: &[Region]) -> String {
+    let mut output = String::new();
+    let mut position = 0;
+    for region in regions {
+        output.push_str(&input[position..region.range.start]);
+        output.push_str(region.original.as_deref().unwrap_or_default());
+        position = region.range.end;
+    }
+    output.push_str(&input[position..]);
+    output
+}
+
+/// Move a code block of the [restored](restore) document to where it is in `input`,
+/// which spans its generated content if it has any.
+pub fn locate(input: &str, regions: &[Region], codeblock: &mut Codeblock) {
+    // How much longer `input` is than the restored document before the current region.
+    let mut growth = 0;
+    for region in regions {
+        let start = region.range.start - growth;
+        let source = region.original.as_deref().unwrap_or_default();
+        if codeblock.range.start < start {
+            break;
+        }
+        if codeblock.range.start < start + source.len() {
+            codeblock.relocate(region.range.clone(), input);
+            return;
+        }
+        growth += region.range.len() - source.len();
+    }
+    let range = codeblock.range.start + growth..codeblock.range.end + growth;
+    codeblock.relocate(range, input);
+}
+
+/// Byte range and markdown of a [located](locate) code block in `input`, which are those of its
+/// generated content if it has any. An indented code block includes its indentation.
+pub fn source<'a>(
+    input: &'a str,
+    regions: &'a [Region],
+    codeblock: &Codeblock,
+) -> (Range<usize>, &'a str) {
+    let generated = regions
+        .iter()
+        .find(|region| region.range == codeblock.range)
+        .and_then(|region| Some((region.range.clone(), region.original.as_deref()?)));
+    if let Some((range, source)) = generated {
+        return (range, source);
+    }
+    let mut range = codeblock.range.clone();
+    if codeblock.fence.is_none() {
+        range.start = input[..range.start].trim_end_matches([' ', '\t']).len();
+    }
+    (range.clone(), &input[range])
+}
+
+/// The markdown of the code block carried by a start comment.
+fn decode(encoded: &str) -> anyhow::Result<String> {
+    let json = STANDARD
+        .decode(encoded)
+        .context("Malformed sentinel comment, the code block is not valid base64")?;
+    let original: Original = serde_json::from_slice(&json)
+        .context("Malformed sentinel comment, the code block cannot be read")?;
+    Ok(original.source)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        pass::{CodeblockPass, PassResult},
+        process_document, Options,
+    };
+
+    struct Rewrite;
+
+    impl CodeblockPass for Rewrite {
+        fn process(&self, codeblock: &Codeblock) -> anyhow::Result<PassResult> {
+            Ok(PassResult {
+                content: Some(codeblock.content.replace("# hidden\n", "")),
+                after: vec!["<p>after</p>\n".into()],
+                ..PassResult::default()
+            })
+        }
+    }
+
+    fn process(input: &str) -> String {
+        let passes: Vec<Box<dyn CodeblockPass>> = vec![Box::new(Rewrite)];
+        process_document(input, &passes, &Options::default()).unwrap()
+    }
+
+    #[test]
+    fn reprocessing_replaces_generated_content() {
+        let input = "# Title  \n\nSome text:\n```rust\n# hidden\nlet a = 1;\n```\n\n* item\n\n  ```text\n  -->\n  ```\n> ~~~\n> quoted\n>\n> ~~~\n\n    indented\n";
+        let processed = process(input);
+        assert!(processed.starts_with("# Title  \n\nSome text:\n<!-- codeblock-processor:start "));
+        assert!(!processed.contains("# hidden"));
+        assert_eq!(process(&processed), processed);
+
+        let stripped = strip(&processed).unwrap();
+        assert!(!contains(&stripped));
+        assert_eq!(stripped, input);
+    }
+
+    #[test]
+    fn locates_code_blocks_in_generated_content() {
+        let processed = process("text\n\n- ```rust\n  let a = 1;\n  ```\n");
+        let regions = regions(&processed).unwrap();
+        assert_eq!(regions.len(), 2);
+        assert_eq!(
+            regions[0].original.as_deref(),
+            Some("```rust\n  let a = 1;\n  ```")
+        );
+        assert_eq!(&processed[regions[0].range.start - 2..][..2], "- ");
+        assert_eq!(regions[1].original, None);
+    }
+
+    #[test]
+    fn rejects_unclosed_generated_content() {
+        let input = format!("{START}e30= -->\n\ntext\n");
+        assert!(strip(&input).is_err());
+    }
+}
@@ -0,0 +1,192 @@
+//! Showing parts of a snippet: a range of its lines, with nested markers collapsed or with function
+//! bodies elided.
+
+use anyhow::{bail, Context};
+use itertools::Itertools;
+use proc_macro2::LineColumn;
+use syn::visit::Visit;
+
+use crate::processor::line_annotator::parse_lines;
+
+/// The lines `spec` selects, such as `3-10` or `7`, counted from 1, and the number of the first one.
+pub fn select_lines(content: &str, spec: &str) -> anyhow::Result<(String, usize)> {
+    let lines = parse_lines(spec).with_context(|| format!("Invalid `lines:{spec}`"))?;
+    let (Some(&start), Some(&end)) = (lines.first(), lines.last()) else {
+        bail!("`lines:` needs a line range, such as `lines:3-10`");
+    };
+    if end + 1 - start != lines.len() {
+        bail!("Cannot show lines {spec}, `lines:` takes a single range");
+    }
+    let line_count = content.lines().count();
+    if start == 0 || end < start || end > line_count {
+        bail!("Cannot show lines {start} to {end}, the snippet has {line_count} lines");
+    }
+    let lines = content
+        .lines()
+        .skip(start - 1)
+        .take(end + 1 - start)
+        .join("\n");
+    Ok((lines, start))
+}
+
+/// Replace the region of the nested marker `name`, including its marker comments, with a line
+/// `{ ... }` at the indentation of the start marker. Regions marked with `#` comments, which are
+/// not used by languages with braces, become `# ...` instead.
+pub fn collapse(content: &str, name: &str) -> anyhow::Result<String> {
+    let marker = |line: &str, kind: &str| {
+        let trimmed = line.trim();
+        ["//", "#"].into_iter().find(|prefix| {
+            trimmed
+                .strip_prefix(prefix)
+                .and_then(|rest| rest.trim_start().strip_prefix(kind))
+                .is_some_and(|rest| rest.trim_end() == name)
+        })
+    };
+    let lines = content.lines().collect::<Vec<_>>();
+    let Some((start, prefix)) = lines
+        .iter()
+        .enumerate()
+        .find_map(|(index, line)| marker(line, "marker-start:").map(|prefix| (index, prefix)))
+    else {
+        bail!("Cannot collapse `{name}`, the snippet has no nested marker of this name");
+    };
+    let Some(end) =
+        (start..lines.len()).find(|&index| marker(lines[index], "marker-end:").is_some())
+    else {
+        bail!("Cannot collapse `{name}`, its marker is not closed in the snippet");
+    };
+    let indentation = &lines[start][..lines[start].len() - lines[start].trim_start().len()];
+    let placeholder = match prefix {
+        "#" => format!("{indentation}# ..."),
+        _ => format!("{indentation}{{ ... }}"),
+    };
+    let mut collapsed = lines[..start].to_vec();
+    collapsed.push(&placeholder);
+    collapsed.extend(&lines[end + 1..]);
+    let mut collapsed = collapsed.join("\n");
+    if content.ends_with('\n') {
+        collapsed.push('\n');
+    }
+    Ok(collapsed)
+}
+
+/// Replace the bodies of functions and methods with `{ .. }`.
+///
+/// The code is parsed as a Rust file, or else as the statements of a function body. Only the
+/// outermost bodies are elided, everything else is kept as it is, including comments.
+pub fn elide_bodies(code: &str) -> anyhow::Result<String> {
+    let mut bodies = Bodies::default();
+    match syn::parse_file(code) {
+        Ok(file) => bodies.visit_file(&file),
+        Err(error) => {
+            let wrapped = format!("fn elided() {{\n{code}\n}}\n");
+            let Ok(file) = syn::parse_file(&wrapped) else {
+                return Err(error).context("Failed to parse Rust code to elide function bodies");
+            };
+            // The code starts on the second line of the wrapper.
+            bodies.line_offset = 1;
+            for item in &file.items {
+                if let syn::Item::Fn(function) = item {
+                    for statement in &function.block.stmts {
+                        bodies.visit_stmt(statement);
+                    }
+                }
+            }
+        }
+    }
+
+    let line_starts = std::iter::once(0)
+        .chain(code.match_indices('\n').map(|(index, _)| index + 1))
+        .collect::<Vec<_>>();
+    let offset = |position: LineColumn| {
+        let start = line_starts[position.line - 1];
+        code[start..]
+            .char_indices()
+            .nth(position.column)
+            .map_or(code.len(), |(index, _)| start + index)
+    };
+    let mut elided = code.to_string();
+    for (start, end) in bodies.ranges.into_iter().rev() {
+        elided.replace_range(offset(start)..offset(end), "{ .. }");
+    }
+    Ok(elided)
+}
+
+/// Collects the positions of the outermost function bodies.
+#[derive(Debug, Default)]
+struct Bodies {
+    /// Lines before the code, in the parsed source.
+    line_offset: usize,
+    ranges: Vec<(LineColumn, LineColumn)>,
+}
+
+impl Bodies {
+    fn push(&mut self, block: &syn::Block) {
+        let position = |mut position: LineColumn| {
+            position.line -= self.line_offset;
+            position
+        };
+        let span = block.brace_token.span;
+        let range = (position(span.open().start()), position(span.close().end()));
+        self.ranges.push(range);
+    }
+}
+
+impl<'ast> Visit<'ast> for Bodies {
+    fn visit_item_fn(&mut self, function: &'ast syn::ItemFn) {
+        self.push(&function.block);
+    }
+
+    fn visit_impl_item_fn(&mut self, function: &'ast syn::ImplItemFn) {
+        self.push(&function.block);
+    }
+
+    fn visit_trait_item_fn(&mut self, function: &'ast syn::TraitItemFn) {
+        if let Some(block) = &function.default {
+            self.push(block);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn selects_and_collapses_lines() {
+        let content = "fn a() {\n    // marker-start:inner\n    let x = 1;\n    // marker-end:inner\n    x\n}\n";
+        assert_eq!(
+            select_lines(content, "2-4").unwrap(),
+            (
+                "    // marker-start:inner\n    let x = 1;\n    // marker-end:inner".to_string(),
+                2
+            )
+        );
+        assert!(select_lines(content, "5-7").is_err());
+        assert_eq!(
+            collapse(content, "inner").unwrap(),
+            "fn a() {\n    { ... }\n    x\n}\n"
+        );
+        assert_eq!(
+            collapse("a = 1\n# marker-start:b\nb = 2\n# marker-end:b\n", "b").unwrap(),
+            "a = 1\n# ...\n"
+        );
+        assert!(collapse(content, "other").is_err());
+        assert!(select_lines(content, "1-2,4").is_err());
+        assert!(select_lines(content, "2-x").is_err());
+    }
+
+    #[test]
+    fn elides_function_bodies() {
+        let code = "/// Doc.\nfn a() -> u32 {\n    1\n}\n\nimpl S {\n    fn b(&self) { todo!(\"ü\") }\n}\n\ntrait T {\n    fn c();\n}\n";
+        assert_eq!(
+            elide_bodies(code).unwrap(),
+            "/// Doc.\nfn a() -> u32 { .. }\n\nimpl S {\n    fn b(&self) { .. }\n}\n\ntrait T {\n    fn c();\n}\n"
+        );
+        assert_eq!(
+            elide_bodies("let x = 1;\nfn f() {\n    g();\n}").unwrap(),
+            "let x = 1;\nfn f() { .. }"
+        );
+        assert!(elide_bodies("fn (").is_err());
+    }
+}
@@ -0,0 +1,243 @@
+use std::{collections::BTreeMap, path::PathBuf, str::FromStr};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+/// Builtin sets of HTML templates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    /// Buttons for Marp slides, with inline styles.
+    #[default]
+    Marp,
+    /// Icon buttons using the Font Awesome icons shipped with mdBook.
+    Mdbook,
+    /// Simple buttons for any HTML page.
+    Plain,
+}
+
+impl FromStr for Preset {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "marp" => Ok(Self::Marp),
+            "mdbook" => Ok(Self::Mdbook),
+            "plain" => Ok(Self::Plain),
+            _ => bail!("Unknown theme preset `{s}`, available are: marp, mdbook, plain"),
+        }
+    }
+}
+
+/// Settings for the HTML around code blocks and buttons.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    pub preset: Preset,
+
+    /// Emit CSS classes (`codeblock`, `codeblock-buttons`, `codeblock-button`) instead of inline styles.
+    pub css_classes: bool,
+
+    /// Directory with template files overriding the preset, such as `button.html`, see [`Theme::load`].
+    /// Relative to the configuration file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub directory: Option<PathBuf>,
+
+    /// Icons by button name, for the `{icon}` placeholder.
+    pub icons: BTreeMap<String, String>,
+}
+
+/// HTML templates for code block wrappers and buttons.
+///
+/// The wrapper templates may contain `{block_id}` and `{language}`. The button template
+/// additionally may contain `{name}`, `{label}`, `{icon}`, `{url}` (a JavaScript expression,
+/// empty for buttons which do not open a URL) and `{onclick}` (the complete click handler),
+/// escaped for double-quoted attributes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    /// Opens the wrapper around a code block and its buttons.
+    pub wrapper_start: String,
+    /// Closes the wrapper.
+    pub wrapper_end: String,
+    /// Opens the group of buttons.
+    pub buttons_start: String,
+    /// Closes the group of buttons.
+    pub buttons_end: String,
+    /// A single button.
+    pub button: String,
+    /// Scripts appended to the document.
+    pub scripts: Vec<String>,
+    /// Icons by button name. A group name like `playground` also applies to `playground:godbolt`.
+    pub icons: BTreeMap<String, String>,
+}
+
+const BLUR_BUTTON: &str = include_str!("blur_button.html");
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::preset(Preset::Marp, false)
+    }
+}
+
+impl Theme {
+    /// The templates of a preset, using CSS classes instead of inline styles if `css_classes` is set.
+    pub fn preset(preset: Preset, css_classes: bool) -> Self {
+        let (wrapper_start, buttons_start) = if css_classes {
+            (
+                r#"<div class="codeblock" id="{block_id}">"#,
+                "<p class=\"codeblock-buttons\">\n",
+            )
+        } else {
+            match preset {
+                Preset::Marp => (
+                    r#"<div style="position: relative;">"#,
+                    "<p style=\"position: absolute; right: 10px; top: 10px; padding: 0; margin: 0; line-height: 0\">\n",
+                ),
+                Preset::Mdbook | Preset::Plain => (
+                    r#"<div id="{block_id}" style="position: relative;">"#,
+                    "<p style=\"position: absolute; right: 0.5em; top: 0.5em; margin: 0;\">\n",
+                ),
+            }
+        };
+        let button = match (preset, css_classes) {
+            (Preset::Marp, false) => "<button\n    onclick=\"{onclick}\"\n    style=\"\n    height: fit-content;\n    margin: 0;\n    font-weight: bold;\"\n>{label}\n</button>\n",
+            (Preset::Mdbook, _) => "<button class=\"fa {icon}\" title=\"{label}\" aria-label=\"{label}\" onclick=\"{onclick}\"></button>\n",
+            (Preset::Plain, false) => "<button type=\"button\" onclick=\"{onclick}\">{label}</button>\n",
+            (Preset::Marp | Preset::Plain, true) => "<button type=\"button\" class=\"codeblock-button codeblock-button-{name}\" onclick=\"{onclick}\">{label}</button>\n",
+        };
+        let scripts = match preset {
+            Preset::Marp => vec![BLUR_BUTTON.to_string()],
+            Preset::Mdbook | Preset::Plain => Vec::new(),
+        };
+        let icons = match preset {
+            Preset::Mdbook => [
+                ("playground", "fa-play"),
+                ("editor", "fa-pencil-square-o"),
+                ("copy", "fa-copy"),
+            ],
+            Preset::Marp | Preset::Plain => [("playground", "▶"), ("editor", "✎"), ("copy", "⧉")],
+        };
+        Self {
+            wrapper_start: wrapper_start.into(),
+            wrapper_end: "</div>\n\n".into(),
+            buttons_start: buttons_start.into(),
+            buttons_end: "</p>\n".into(),
+            button: button.into(),
+            scripts,
+            icons: icons
+                .into_iter()
+                .map(|(name, icon)| (name.to_string(), icon.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Build the theme from its configuration, reading template files from its directory.
+    ///
+    /// The files `wrapper_start.html`, `wrapper_end.html`, `buttons_start.html`, `buttons_end.html`,
+    /// `button.html` and `scripts.html` replace the corresponding template of the preset if they exist.
+    pub fn load(config: &ThemeConfig) -> anyhow::Result<Self> {
+        let mut theme = Self::preset(config.preset, config.css_classes);
+        theme.icons.extend(config.icons.clone());
+        let Some(directory) = &config.directory else {
+            return Ok(theme);
+        };
+        let read = |name: &str| -> anyhow::Result<Option<String>> {
+            let path = directory.join(name);
+            if !path.exists() {
+                return Ok(None);
+            }
+            std::fs::read_to_string(&path)
+                .map(Some)
+                .with_context(|| format!("Failed to read template {}", path.display()))
+        };
+        for (name, template) in [
+            ("wrapper_start.html", &mut theme.wrapper_start),
+            ("wrapper_end.html", &mut theme.wrapper_end),
+            ("buttons_start.html", &mut theme.buttons_start),
+            ("buttons_end.html", &mut theme.buttons_end),
+            ("button.html", &mut theme.button),
+        ] {
+            if let Some(text) = read(name)? {
+                *template = text;
+            }
+        }
+        if let Some(scripts) = read("scripts.html")? {
+            theme.scripts = vec![scripts];
+        }
+        Ok(theme)
+    }
+
+    /// The icon for a button, looked up by its name and then by its group.
+    pub fn icon(&self, name: &str) -> &str {
+        self.icons
+            .get(name)
+            .or_else(|| {
+                name.split_once(':')
+                    .and_then(|(group, _)| self.icons.get(group))
+            })
+            .map_or("", String::as_str)
+    }
+}
+
+/// Fill `{key}` placeholders in a template. Unknown placeholders are kept as they are.
+pub fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest.find('}').and_then(|end| {
+            values
+                .iter()
+                .find(|(key, _)| *key == &rest[1..end])
+                .map(|(_, value)| (end, value))
+        });
+        match value {
+            Some((end, value)) => {
+                output.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                output.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fills_placeholders_once() {
+        assert_eq!(
+            fill("{a} {b} {c} {", &[("a", "{b}"), ("b", "x")]),
+            "{b} x {c} {"
+        );
+    }
+
+    #[test]
+    fn loads_template_files() {
+        let directory = tempfile::tempdir().unwrap();
+        std::fs::write(
+            directory.path().join("button.html"),
+            "<a onclick=\"{onclick}\">{icon}</a>",
+        )
+        .unwrap();
+        let config = ThemeConfig {
+            preset: Preset::Plain,
+            directory: Some(directory.path().to_path_buf()),
+            icons: BTreeMap::from([("copy".to_string(), "C".to_string())]),
+            ..ThemeConfig::default()
+        };
+        let theme = Theme::load(&config).unwrap();
+        assert_eq!(theme.button, "<a onclick=\"{onclick}\">{icon}</a>");
+        assert_eq!(theme.wrapper_end, "</div>\n\n");
+        assert_eq!(theme.icon("copy"), "C");
+        assert_eq!(theme.icon("playground:godbolt"), "▶");
+        assert_eq!(theme.icon("unknown"), "");
+    }
+}
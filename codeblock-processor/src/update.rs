@@ -0,0 +1,170 @@
+//! Write-back mode: refresh the code of `marker:` and `include:` blocks in the source markdown,
+//! so that it reads well where it is not processed, such as on GitHub.
+
+use anyhow::bail;
+use pulldown_cmark::Parser;
+
+use crate::{
+    aggregation::Aggregation, codeblock::Codeblock, codeblock_aggregator::CodeblockTools,
+    pass::CodeblockPass, processor::snippet_button_inserter::SnippetButtonInserter,
+    validate::Diagnostic,
+};
+
+/// The result of updating a document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Update {
+    /// The document with the current code in its `marker:` and `include:` blocks.
+    /// Everything else is kept byte for byte.
+    pub output: String,
+    /// The blocks whose code was out of date.
+    pub stale: Vec<Diagnostic>,
+}
+
+/// Replace the content of each `marker:` and `include:` block of `input` with the code it refers to,
+/// as `inserter` would show it.
+pub fn update(input: &str, inserter: &SnippetButtonInserter) -> anyhow::Result<Update> {
+    let mut replacements = Vec::new();
+    let mut stale = Vec::new();
+    let codeblocks = Parser::new(input)
+        .into_offset_iter()
+        .aggregate_codeblocks(input)
+        .filter_map(|aggregation| match aggregation {
+            Aggregation::Codeblock(codeblock) => Some(codeblock),
+            Aggregation::Event(_) => None,
+        });
+    for codeblock in codeblocks {
+        let info = &codeblock.info;
+        let attribute = match (info.get("marker"), info.get("include")) {
+            (Some(marker), _) => format!("marker:{marker}"),
+            (None, Some(include)) => format!("include:{include}"),
+            (None, None) => continue,
+        };
+        let location = || format!("code block at line {}:{}", codeblock.line, codeblock.column);
+        let Some(mut content) = inserter
+            .process(&codeblock)
+            .map_err(|error| error.context(format!("Failed to update {}", location())))?
+            .content
+        else {
+            continue;
+        };
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        if content == codeblock.content {
+            continue;
+        }
+        let (range, prefix) = content_range(input, &codeblock)?;
+        let fence = fence_of(&input[codeblock.range.clone()]);
+        if content
+            .lines()
+            .any(|line| line.trim_start().starts_with(fence))
+        {
+            bail!(
+                "Cannot update {}, the code contains its fence `{fence}`, use a longer fence",
+                location()
+            );
+        }
+        let mut replacement = String::new();
+        for line in content.lines() {
+            let prefix = if line.is_empty() {
+                prefix.trim_end()
+            } else {
+                prefix
+            };
+            replacement.push_str(prefix);
+            replacement.push_str(line);
+            replacement.push('\n');
+        }
+        replacements.push((range, replacement));
+        stale.push(Diagnostic {
+            line: codeblock.line,
+            column: codeblock.column,
+            message: format!("Code of `{attribute}` is out of date"),
+        });
+    }
+
+    let mut output = input.to_string();
+    for (range, replacement) in replacements.into_iter().rev() {
+        output.replace_range(range, &replacement);
+    }
+    Ok(Update { output, stale })
+}
+
+/// The backticks or tildes opening the code block.
+fn fence_of(block: &str) -> &str {
+    let opening = block.trim_start();
+    let char = opening.chars().next().unwrap_or('`');
+    let length = opening.len() - opening.trim_start_matches(char).len();
+    &opening[..length]
+}
+
+/// The byte range of the lines between the fences, and the prefix of the closing fence line, such as
+/// the indentation in a list item or `> ` in a block quote, which each line of code must start with.
+fn content_range<'a>(
+    input: &'a str,
+    codeblock: &Codeblock,
+) -> anyhow::Result<(std::ops::Range<usize>, &'a str)> {
+    let block = &input[codeblock.range.clone()];
+    let fence = fence_of(block);
+    let Some(first_line_end) = block.find('\n') else {
+        bail!(
+            "Cannot update the code block at line {}, it has no content lines",
+            codeblock.line
+        );
+    };
+    let start = codeblock.range.start + first_line_end + 1;
+    let body = block[first_line_end + 1..].trim_end_matches(['\n', '\r']);
+    let last_line_start = body.rfind('\n').map_or(0, |newline| newline + 1);
+    let last_line = &body[last_line_start..];
+    let closing = last_line.find(fence).filter(|&index| {
+        let rest = last_line[index..].trim_end();
+        rest.len() >= fence.len() && rest.chars().all(|c| fence.starts_with(c))
+    });
+    match closing {
+        Some(index) => Ok((start..start + last_line_start, &last_line[..index])),
+        None => {
+            // An unclosed code block runs to the end of the document,
+            // the opening line gives the indentation.
+            let opening = &block[..first_line_end];
+            let indentation = opening.len() - opening.trim_start().len();
+            Ok((start..codeblock.range.end, &opening[..indentation]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use snippet_extractor::Snippets;
+
+    use super::*;
+
+    #[test]
+    fn rewrites_only_stale_blocks() {
+        let directory = tempfile::tempdir().unwrap();
+        std::fs::write(directory.path().join("lib.rs"), "fn a() {\n\n    1\n}\n").unwrap();
+        let inserter = SnippetButtonInserter::with_snippets(Snippets::default())
+            .with_include_base(directory.path().to_path_buf());
+
+        let input = "# Title  \n\n```rust include:lib.rs\nold\n```\n\n> ```rust include:lib.rs#L1\n> fn a() {\n> ```\n\n- ```text\n  kept\n  ```\n";
+        let update = update(input, &inserter).unwrap();
+        assert_eq!(
+            update.output,
+            "# Title  \n\n```rust include:lib.rs\nfn a() {\n\n    1\n}\n```\n\n> ```rust include:lib.rs#L1\n> fn a() {\n> ```\n\n- ```text\n  kept\n  ```\n"
+        );
+        assert_eq!(
+            update
+                .stale
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["3:1: Code of `include:lib.rs` is out of date"]
+        );
+
+        let listed = "- ```rust include:lib.rs\n  ```\n";
+        assert_eq!(
+            super::update(listed, &inserter).unwrap().output,
+            "- ```rust include:lib.rs\n  fn a() {\n\n      1\n  }\n  ```\n"
+        );
+        assert_eq!(super::update(&update.output, &inserter).unwrap().stale, []);
+    }
+}
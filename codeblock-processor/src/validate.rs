@@ -0,0 +1,188 @@
+//! Checks for fence attributes which would otherwise be ignored silently, such as misspelled markers.
+
+use std::{collections::BTreeSet, fmt};
+
+use itertools::Itertools;
+use pulldown_cmark::Parser;
+use snippet_extractor::Snippets;
+
+use crate::{
+    aggregation::Aggregation, codeblock::Codeblock, codeblock_aggregator::CodeblockTools, sentinel,
+};
+
+/// Values of `tag:` which a pass acts on.
+pub const TAGS: &[&str] = &["playground-button"];
+
+/// A problem with a code block, located in the markdown document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// 1-based line.
+    pub line: usize,
+    /// 1-based column.
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// Checks the code blocks of a document for unknown markers, tags and playground templates,
+/// and for malformed attributes.
+#[derive(Debug, Clone, Default)]
+pub struct Validator {
+    /// Known snippet ids, `None` if no snippets were given.
+    markers: Option<BTreeSet<String>>,
+    templates: BTreeSet<String>,
+}
+
+impl Validator {
+    /// Check `marker:` attributes against these snippets.
+    #[must_use]
+    pub fn with_snippets(mut self, snippets: &Snippets) -> Self {
+        self.markers = Some(snippets.iter().map(|(id, _)| id.to_string()).collect());
+        self
+    }
+
+    /// Check `playground-wrap:` attributes against these template names.
+    #[must_use]
+    pub fn with_templates<'a>(mut self, names: impl IntoIterator<Item = &'a String>) -> Self {
+        self.templates = names.into_iter().cloned().collect();
+        self
+    }
+
+    /// All problems of the fenced code blocks of `input`, in document order.
+    pub fn validate(&self, input: &str) -> Vec<Diagnostic> {
+        // Code blocks of generated output are checked as in the source, at their place in `input`.
+        let regions = sentinel::regions(input).unwrap_or_default();
+        let restored = sentinel::restore(input, &regions);
+        Parser::new(&restored)
+            .into_offset_iter()
+            .aggregate_codeblocks(&restored)
+            .filter_map(|aggregation| match aggregation {
+                Aggregation::Codeblock(codeblock) => Some(codeblock),
+                Aggregation::Event(_) => None,
+            })
+            .flat_map(|mut codeblock| {
+                sentinel::locate(input, &regions, &mut codeblock);
+                let (_, source) = sentinel::source(input, &regions, &codeblock);
+                self.check(&codeblock, source)
+            })
+            .collect()
+    }
+
+    /// Check a code block, written as `source` in the markdown.
+    fn check(&self, codeblock: &Codeblock, source: &str) -> Vec<Diagnostic> {
+        let Some(fence) = &codeblock.fence else {
+            return Vec::new();
+        };
+        // Attribute spans are relative to the info string, which follows the backticks.
+        let opening_line = source.lines().next().unwrap_or_default();
+        let info_offset = opening_line.find(fence.as_str()).unwrap_or_default();
+        let diagnostic = |offset: usize, message: String| Diagnostic {
+            line: codeblock.line,
+            column: codeblock.column + info_offset + offset,
+            message,
+        };
+
+        let mut diagnostics = codeblock
+            .fence_errors
+            .iter()
+            .map(|error| {
+                diagnostic(
+                    error.span.start,
+                    format!("Malformed fence attribute: {}", error.message),
+                )
+            })
+            .collect::<Vec<_>>();
+        for attribute in &codeblock.info.attributes {
+            let value = attribute.value.as_deref().unwrap_or_default();
+            let message = match attribute.key.as_str() {
+                "marker" => match &self.markers {
+                    None => Some(format!("Unknown marker `{value}`, no snippets were given")),
+                    Some(markers) if !markers.contains(value) => {
+                        Some(format!("Unknown marker `{value}`"))
+                    }
+                    Some(_) => None,
+                },
+                "tag" if !TAGS.contains(&value) => Some(format!(
+                    "Unknown tag `{value}`, available are: {}",
+                    TAGS.join(", ")
+                )),
+                "playground-wrap" if !self.templates.contains(value) => Some(format!(
+                    "Unknown playground template `{value}`, available are: {}",
+                    self.templates.iter().join(", ")
+                )),
+                "part" if !codeblock.info.has("playground-group") => Some(format!(
+                    "`part:{value}` has no effect without `playground-group:`"
+                )),
+                _ => None,
+            };
+            if let Some(message) = message {
+                diagnostics.push(diagnostic(attribute.span.start, message));
+            }
+        }
+        diagnostics.sort_by_key(|diagnostic| diagnostic.column);
+        diagnostics
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::BTreeMap, path::PathBuf};
+
+    use snippet_extractor::Snippet;
+
+    use super::*;
+    use crate::{process_document, processor::playground_button_inserter::Template, Options};
+
+    #[test]
+    fn reports_unknown_names_with_positions() {
+        let snippets = Snippets {
+            base: None,
+            snippets: BTreeMap::from([(
+                PathBuf::from("a.rs"),
+                BTreeMap::from([(
+                    "setup".to_string(),
+                    Snippet {
+                        content: String::new(),
+                        file: "a.rs".into(),
+                        line: 0,
+                        col: 0,
+                        language: None,
+                    },
+                )]),
+            )]),
+        };
+        let validator = Validator::default()
+            .with_snippets(&snippets)
+            .with_templates(Template::builtin().keys());
+        let input = "# Title\n\n```rust marker:setup\n```\n\n- ```rust marker:typo tag:playground-buton\n  ```\n\n```rust playground-wrap:mian :oops\n```\n\n```rust part:1\n```\n";
+        let diagnostics = validator
+            .validate(input)
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            [
+                "6:11: Unknown marker `typo`",
+                "6:23: Unknown tag `playground-buton`, available are: playground-button",
+                "9:9: Unknown playground template `mian`, available are: main, main_anyhow, main_tokio_anyhow",
+                "9:30: Malformed fence attribute: Attribute without key",
+                "12:9: `part:1` has no effect without `playground-group:`",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_positions_in_generated_output() {
+        let input = "# Title\n\n- text\n\n  ```rust tag:typo\n  ```\n";
+        let output = process_document(input, &[], &Options::default()).unwrap();
+        let diagnostics = Validator::default().validate(&output);
+        assert_eq!(diagnostics, Validator::default().validate(input));
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (5, 11));
+    }
+}
@@ -25,6 +25,11 @@ struct Arguments {
     #[arg(short, long)]
     example: bool,
 
+    /// Print the JSON Schema of the configuration file format?
+    /// No further action is taken.
+    #[arg(long)]
+    schema: bool,
+
     /// The output file, or stdout if not specified.
     #[arg(short, long)]
     output: Option<PathBuf>,
@@ -43,8 +48,13 @@ fn main() -> anyhow::Result<()> {
         return Ok(());
     }
 
+    if args.schema {
+        println!("{}", Transformers::json_schema()?);
+        return Ok(());
+    }
+
     let regex_replacers = if let Some(config) = args.config {
-        toml::from_str::<Transformers>(
+        Transformers::from_toml(
             &fs::read_to_string(config).context("Failed to read transformer config file")?,
         )
         .context("Failed to deserialize toml config file")?
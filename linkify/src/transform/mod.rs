@@ -1,6 +1,8 @@
 use std::fmt::Debug;
 
+use anyhow::{bail, Context};
 use regex::Regex;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::link::Link;
@@ -31,8 +33,18 @@ pub trait LinkTransformer: Debug + dyn_clone::DynClone {
 
 dyn_clone::clone_trait_object!(LinkTransformer);
 
-#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+/// Current version of the `linkify.toml` format.
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Contents of `linkify.toml`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[schemars(title = "linkify.toml")]
 pub struct Transformers {
+    /// Format version of this file. Unversioned files are treated as version 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
+
+    /// Regex-based link substitutions.
     pub regex: Vec<Substitution>,
 }
 
@@ -41,7 +53,64 @@ impl Transformers {
     #[must_use]
     pub fn example() -> Self {
         Self {
+            version: Some(CONFIG_VERSION),
             regex: vec![Substitution::example()],
         }
     }
+
+    /// Read `linkify.toml`, rejecting unknown future format versions.
+    pub fn from_toml(config: &str) -> anyhow::Result<Self> {
+        let value = toml::from_str::<toml::Value>(config).context("Invalid TOML")?;
+        let version = match value.get("version") {
+            None => 1,
+            Some(version) => version
+                .as_integer()
+                .context("Format version must be a positive integer")?,
+        };
+        match version {
+            1 => value
+                .try_into()
+                .context("Failed to parse linkify config (format version 1)"),
+            version if version > i64::from(CONFIG_VERSION) => bail!(
+                "Unsupported linkify config format version {version}, this tool supports versions up to {CONFIG_VERSION}. Please update markdown-linkify."
+            ),
+            version => bail!("Invalid linkify config format version {version}"),
+        }
+    }
+
+    /// The JSON Schema of `linkify.toml`.
+    pub fn json_schema() -> anyhow::Result<String> {
+        let schema = schemars::schema_for!(Transformers);
+        Ok(serde_json::to_string_pretty(&schema)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_unversioned_config() {
+        let transformers = Transformers::from_toml(include_str!("../../linkify.toml")).unwrap();
+        assert_eq!(transformers.version, None);
+        assert_eq!(transformers.regex.len(), 3);
+    }
+
+    #[test]
+    fn rejects_future_versions() {
+        let error = Transformers::from_toml("version = 7\nregex = []")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("Unsupported linkify config format version 7"));
+    }
+
+    #[test]
+    fn published_schema_is_up_to_date() {
+        let schema = Transformers::json_schema().unwrap();
+        assert_eq!(
+            include_str!("../../linkify.schema.json").trim_end(),
+            schema,
+            "linkify.schema.json is outdated, regenerate it with `markdown-linkify --schema`"
+        );
+    }
 }
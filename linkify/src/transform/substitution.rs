@@ -1,25 +1,36 @@
 use pulldown_cmark::{Event, LinkType};
 use regex::Regex;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::{link::Link, LinkTransformer};
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct Substitution {
+    /// Regex matching the part of the link after the tag.
     #[serde(with = "serde_regex")]
+    #[schemars(with = "String")]
     tail: Regex,
+
+    /// Prefix which identifies the link shorthand, such as `PS-`.
     tag: String,
+
+    /// Replacement link, may refer to regex captures such as `$text`.
     replacement: String,
 
+    /// Maximum number of replacements, 0 means unlimited.
     #[serde(default = "one")]
     limit: usize,
 
+    /// Render the link text as inline code.
     #[serde(default)]
     code: bool,
 
+    /// Strip the tag from the link text.
     #[serde(default)]
     tail_only: bool,
 
+    /// Replace existing link text.
     #[serde(default)]
     replace_text: bool,
 }
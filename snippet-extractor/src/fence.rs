@@ -0,0 +1,392 @@
+use std::{fmt, ops::Range, str::FromStr};
+
+/// Parsed info string of a fenced code block.
+///
+/// The grammar is: an optional language as first word, followed by whitespace-separated attributes.
+/// An attribute is a bare flag, `key:value`, `key=value`, or a quoted value `key:"quoted value"`
+/// (also `key="quoted value"`). Quoted values may escape `"` and `\` with a backslash.
+/// The legacy form `key:$"value"$` is accepted as well.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FenceInfo {
+    pub language: Option<String>,
+    pub attributes: Vec<Attribute>,
+}
+
+/// A fence attribute. Flags have no value.
+#[derive(Debug, Clone, Eq)]
+pub struct Attribute {
+    pub key: String,
+    pub value: Option<String>,
+    /// Byte range of the attribute in the info string.
+    pub span: Range<usize>,
+}
+
+/// Attributes are equal if their keys and values are equal, regardless of where they were parsed from.
+impl PartialEq for Attribute {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key && self.value == other.value
+    }
+}
+
+/// A syntax error in a fence info string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FenceError {
+    /// Byte range of the offending text in the info string.
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for FenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (at {}..{})",
+            self.message, self.span.start, self.span.end
+        )
+    }
+}
+
+impl std::error::Error for FenceError {}
+
+impl FenceInfo {
+    /// Parse an info string, failing on the first syntax error.
+    pub fn parse(info: &str) -> Result<Self, FenceError> {
+        let (fence, mut errors) = Self::parse_lossy(info);
+        if errors.is_empty() {
+            Ok(fence)
+        } else {
+            Err(errors.remove(0))
+        }
+    }
+
+    /// Parse an info string, skipping malformed attributes and collecting their errors.
+    pub fn parse_lossy(info: &str) -> (Self, Vec<FenceError>) {
+        let mut parser = InfoParser { info, position: 0 };
+        let mut fence = Self::default();
+        let mut errors = Vec::new();
+        let mut first = true;
+        while let Some(token) = parser.next_token() {
+            match token {
+                Ok(attribute) if first && attribute.value.is_none() => {
+                    fence.language = Some(attribute.key);
+                }
+                Ok(attribute) => fence.attributes.push(attribute),
+                Err(error) => errors.push(error),
+            }
+            first = false;
+        }
+        (fence, errors)
+    }
+
+    /// The value of the last attribute with this key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .rev()
+            .find(|attribute| attribute.key == key)
+            .and_then(|attribute| attribute.value.as_deref())
+    }
+
+    /// The values of all attributes with this key, in order.
+    pub fn values<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.attributes
+            .iter()
+            .filter(move |attribute| attribute.key == key)
+            .filter_map(|attribute| attribute.value.as_deref())
+    }
+
+    /// Whether the flag (or an attribute with this key) is present.
+    pub fn has(&self, key: &str) -> bool {
+        self.attributes.iter().any(|attribute| attribute.key == key)
+    }
+
+    /// The attribute with this key, for its span.
+    pub fn attribute(&self, key: &str) -> Option<&Attribute> {
+        self.attributes
+            .iter()
+            .rev()
+            .find(|attribute| attribute.key == key)
+    }
+}
+
+impl FromStr for FenceInfo {
+    type Err = FenceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+/// Renders the canonical form of the info string, which parses back to the same [`FenceInfo`].
+impl fmt::Display for FenceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut separator = "";
+        if let Some(language) = &self.language {
+            write!(f, "{language}")?;
+            separator = " ";
+        }
+        for attribute in &self.attributes {
+            write!(f, "{separator}{attribute}")?;
+            separator = " ";
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Attribute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(value) = &self.value else {
+            return write!(f, "{}", self.key);
+        };
+        let needs_quotes = value.is_empty()
+            || value.starts_with("$\"")
+            || value
+                .chars()
+                .any(|c| c.is_whitespace() || c == '"' || c == '\\');
+        if needs_quotes {
+            let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+            write!(f, "{}=\"{escaped}\"", self.key)
+        } else {
+            write!(f, "{}:{value}", self.key)
+        }
+    }
+}
+
+struct InfoParser<'a> {
+    info: &'a str,
+    position: usize,
+}
+
+impl<'a> InfoParser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.info[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    /// Advance to the next whitespace, returning the span from `start`.
+    fn skip_word(&mut self, start: usize) -> Range<usize> {
+        let rest = self.rest();
+        self.position += rest.find(char::is_whitespace).unwrap_or(rest.len());
+        start..self.position
+    }
+
+    fn error(&mut self, start: usize, message: String) -> Option<Result<Attribute, FenceError>> {
+        let span = self.skip_word(start);
+        Some(Err(FenceError { span, message }))
+    }
+
+    fn next_token(&mut self) -> Option<Result<Attribute, FenceError>> {
+        self.skip_whitespace();
+        let start = self.position;
+        let rest = self.rest();
+        if rest.is_empty() {
+            return None;
+        }
+        let key_end = rest
+            .find(|c: char| c.is_whitespace() || c == ':' || c == '=' || c == '"')
+            .unwrap_or(rest.len());
+        let key = &rest[..key_end];
+        self.position += key_end;
+        let separator = self.rest().chars().next();
+        match separator {
+            None => {}
+            Some(c) if c.is_whitespace() => {}
+            Some('"') => {
+                return self.error(start, "Unexpected quote, quoted values need a key".into())
+            }
+            Some(_) => {
+                if key.is_empty() {
+                    return self.error(start, "Attribute without key".into());
+                }
+                if !key
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))
+                {
+                    return self.error(start, format!("Invalid attribute key `{key}`"));
+                }
+                self.position += 1;
+                let value = match self.value(start, key) {
+                    Ok(value) => value,
+                    Err(error) => return Some(Err(error)),
+                };
+                return Some(Ok(Attribute {
+                    key: key.to_string(),
+                    value: Some(value),
+                    span: start..self.position,
+                }));
+            }
+        }
+        Some(Ok(Attribute {
+            key: key.to_string(),
+            value: None,
+            span: start..self.position,
+        }))
+    }
+
+    fn value(&mut self, start: usize, key: &str) -> Result<String, FenceError> {
+        let rest = self.rest();
+        if let Some(legacy) = rest.strip_prefix("$\"") {
+            let Some(end) = legacy.find("\"$") else {
+                self.position = self.info.len();
+                return Err(FenceError {
+                    span: start..self.position,
+                    message: format!("Unterminated value for `{key}`, expected `\"$`"),
+                });
+            };
+            self.position += 2 + end + 2;
+            return self.end_of_quoted(start, legacy[..end].to_string());
+        }
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let mut value = String::new();
+            let mut chars = quoted.char_indices();
+            while let Some((index, c)) = chars.next() {
+                match c {
+                    '"' => {
+                        self.position += 1 + index + 1;
+                        return self.end_of_quoted(start, value);
+                    }
+                    '\\' => match chars.next() {
+                        Some((_, escaped @ ('"' | '\\'))) => value.push(escaped),
+                        Some((_, other)) => {
+                            value.push('\\');
+                            value.push(other);
+                        }
+                        None => value.push('\\'),
+                    },
+                    c => value.push(c),
+                }
+            }
+            self.position = self.info.len();
+            return Err(FenceError {
+                span: start..self.position,
+                message: format!("Unterminated quoted value for `{key}`"),
+            });
+        }
+        let value_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        if value_end == 0 {
+            return Err(FenceError {
+                span: start..self.position,
+                message: format!("Missing value for `{key}`"),
+            });
+        }
+        self.position += value_end;
+        Ok(rest[..value_end].to_string())
+    }
+
+    fn end_of_quoted(&mut self, start: usize, value: String) -> Result<String, FenceError> {
+        match self.rest().chars().next() {
+            Some(c) if !c.is_whitespace() => {
+                let span = self.skip_word(start);
+                Err(FenceError {
+                    span,
+                    message: "Unexpected text after quoted value".into(),
+                })
+            }
+            _ => Ok(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn attribute(key: &str, value: Option<&str>) -> Attribute {
+        Attribute {
+            key: key.into(),
+            value: value.map(Into::into),
+            span: 0..0,
+        }
+    }
+
+    #[test]
+    fn parses_language_values_and_flags() {
+        let fence = FenceInfo::parse(
+            r#"rust tag:playground-button playground-wrap="main" hide_other_markers title:"Hello, \"world\"""#,
+        )
+        .unwrap();
+        assert_eq!(fence.language.as_deref(), Some("rust"));
+        assert_eq!(
+            fence.attributes,
+            vec![
+                attribute("tag", Some("playground-button")),
+                attribute("playground-wrap", Some("main")),
+                attribute("hide_other_markers", None),
+                attribute("title", Some("Hello, \"world\"")),
+            ]
+        );
+        assert_eq!(fence.get("tag"), Some("playground-button"));
+        assert!(fence.has("hide_other_markers"));
+        assert_eq!(fence.attributes[1].span, 27..49);
+    }
+
+    #[test]
+    fn fence_without_language() {
+        let fence = FenceInfo::parse("marker:setup hide_other_markers").unwrap();
+        assert_eq!(fence.language, None);
+        assert_eq!(fence.get("marker"), Some("setup"));
+        assert_eq!(FenceInfo::parse("").unwrap(), FenceInfo::default());
+    }
+
+    #[test]
+    fn tag_inside_values_is_not_an_attribute() {
+        let fence = FenceInfo::parse(r#"md title:"a tag:other" tag:playground-button"#).unwrap();
+        assert_eq!(fence.attributes.len(), 2);
+        assert_eq!(
+            fence.values("tag").collect::<Vec<_>>(),
+            ["playground-button"]
+        );
+    }
+
+    #[test]
+    fn parses_legacy_dollar_quotes() {
+        let fence = FenceInfo::parse(
+            r#"rust tag:playground-button playground-before:$"fn main() {"$ playground-after:$"}"$"#,
+        )
+        .unwrap();
+        assert_eq!(fence.get("playground-before"), Some("fn main() {"));
+        assert_eq!(fence.get("playground-after"), Some("}"));
+    }
+
+    #[test]
+    fn reports_error_spans() {
+        let error = FenceInfo::parse(r#"rust title:"unterminated"#).unwrap_err();
+        assert_eq!(error.span, 5..24);
+        let error = FenceInfo::parse("rust tag:").unwrap_err();
+        assert_eq!(error.message, "Missing value for `tag`");
+        assert_eq!(error.span, 5..9);
+        let error = FenceInfo::parse("rust :value").unwrap_err();
+        assert_eq!(error.span, 5..11);
+        let error = FenceInfo::parse(r#"rust a:"b"c"#).unwrap_err();
+        assert_eq!(error.message, "Unexpected text after quoted value");
+    }
+
+    #[test]
+    fn lossy_parsing_skips_malformed_attributes() {
+        let (fence, errors) = FenceInfo::parse_lossy("rust :oops marker:setup");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(fence.get("marker"), Some("setup"));
+    }
+
+    #[test]
+    fn round_trips() {
+        let inputs = [
+            "rust",
+            "rust tag:playground-button playground-indent",
+            r#"rust title="with spaces" empty="" path:C:\dir"#,
+            r#"text note="quote \" and backslash \\" legacy:$"a b"$"#,
+            "marker:setup",
+        ];
+        for input in inputs {
+            let fence = FenceInfo::parse(input).unwrap();
+            let canonical = fence.to_string();
+            let reparsed = FenceInfo::parse(&canonical).unwrap();
+            assert_eq!(reparsed, fence, "{input} -> {canonical}");
+            assert_eq!(reparsed.to_string(), canonical);
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use anyhow::{bail, Context};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{language, Snippets};
+
+/// Current version of the `snippets.json` format.
+///
+/// Version 1 is the unversioned format without snippet languages.
+/// Version 2 has no base directory for the snippet file paths.
+pub const FORMAT_VERSION: u32 = 3;
+
+/// On-disk representation of `snippets.json`: all snippets plus a format version.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(title = "snippets.json")]
+pub struct SnippetsFile {
+    /// Format version of this file.
+    pub version: u32,
+
+    #[serde(flatten)]
+    pub snippets: Snippets,
+}
+
+impl Snippets {
+    /// Read `snippets.json`, migrating older format versions.
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let value = serde_json::from_str::<serde_json::Value>(json).context("Invalid JSON")?;
+        let version = match value.get("version") {
+            None => 1,
+            Some(version) => version
+                .as_u64()
+                .context("Format version must be a positive integer")?,
+        };
+        match version {
+            1 => {
+                let snippets = serde_json::from_value::<Snippets>(value)
+                    .context("Failed to parse snippets (format version 1)")?;
+                Ok(migrate_v1(snippets))
+            }
+            // Version 2 files have no base, which is what an unset base means.
+            2 | 3 => {
+                let file = serde_json::from_value::<SnippetsFile>(value)
+                    .with_context(|| format!("Failed to parse snippets (format version {version})"))?;
+                Ok(file.snippets)
+            }
+            version if version > u64::from(FORMAT_VERSION) => bail!(
+                "Unsupported snippets format version {version}, this tool supports versions up to {FORMAT_VERSION}. Please update snippet-extractor and markdown-codeblock-processor."
+            ),
+            version => bail!("Invalid snippets format version {version}"),
+        }
+    }
+
+    /// Write `snippets.json` in the current format version.
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        let file = SnippetsFile {
+            version: FORMAT_VERSION,
+            snippets: self.clone(),
+        };
+        Ok(serde_json::to_string_pretty(&file)?)
+    }
+
+    /// The JSON Schema of `snippets.json`.
+    pub fn json_schema() -> anyhow::Result<String> {
+        let schema = schemars::schema_for!(SnippetsFile);
+        Ok(serde_json::to_string_pretty(&schema)?)
+    }
+}
+
+/// Version 1 did not record snippet languages, so detect them from the file names.
+fn migrate_v1(mut snippets: Snippets) -> Snippets {
+    for file_snippets in snippets.snippets.values_mut() {
+        for snippet in file_snippets.values_mut() {
+            if snippet.language.is_none() {
+                snippet.language = language::detect(&snippet.file.to_path(""), "");
+            }
+        }
+    }
+    snippets
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn migrates_unversioned_format() {
+        let json = r#"{
+            "snippets": {
+                "src/main.rs": {
+                    "setup": { "content": "let a = 4;", "file": "src/main.rs", "line": 3, "col": 0 }
+                }
+            }
+        }"#;
+        let snippets = Snippets::from_json(json).unwrap();
+        let setup = &snippets.snippets_for_id("setup")[0];
+        assert_eq!(setup.content, "let a = 4;");
+        assert_eq!(setup.language.as_deref(), Some("rust"));
+        assert_eq!(
+            snippets.absolute_path(setup),
+            std::env::current_dir().unwrap().join("src/main.rs")
+        );
+    }
+
+    #[test]
+    fn round_trips_current_format() {
+        let json = r#"{ "version": 3, "base": "/work", "snippets": { "a.py": { "x": { "content": "pass", "file": "a.py", "line": 0, "col": 0, "language": "python" } } } }"#;
+        let snippets = Snippets::from_json(json).unwrap();
+        let written = snippets.to_json().unwrap();
+        assert!(written.contains("\"version\": 3"));
+        assert_eq!(Snippets::from_json(&written).unwrap(), snippets);
+        let x = &snippets.snippets_for_id("x")[0];
+        assert_eq!(snippets.absolute_path(x), Path::new("/work/a.py"));
+    }
+
+    #[test]
+    fn rejects_future_versions() {
+        let json = r#"{ "version": 99, "snippets": {} }"#;
+        let error = Snippets::from_json(json).unwrap_err().to_string();
+        assert!(error.contains("Unsupported snippets format version 99"));
+    }
+
+    #[test]
+    fn published_schema_is_up_to_date() {
+        let schema = Snippets::json_schema().unwrap();
+        assert_eq!(
+            include_str!("../snippets.schema.json").trim_end(),
+            schema,
+            "snippets.schema.json is outdated, regenerate it with `snippet-extractor schema`"
+        );
+    }
+}
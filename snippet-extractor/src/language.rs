@@ -0,0 +1,117 @@
+use std::path::Path;
+
+/// Detect the language of a source file, for use as a markdown fence language.
+///
+/// The file name and extension are checked first, then a shebang on the first line.
+pub fn detect(file: &Path, content: &str) -> Option<String> {
+    from_path(file)
+        .or_else(|| from_shebang(content))
+        .map(ToString::to_string)
+}
+
+fn from_path(file: &Path) -> Option<&'static str> {
+    let name = file.file_name()?.to_str()?;
+    let language = match name {
+        "justfile" | "Justfile" | ".justfile" => "just",
+        "Makefile" | "makefile" | "GNUmakefile" => "makefile",
+        "Dockerfile" => "dockerfile",
+        "CMakeLists.txt" => "cmake",
+        _ => {
+            let extension = file.extension()?.to_str()?;
+            from_extension(extension)?
+        }
+    };
+    Some(language)
+}
+
+fn from_extension(extension: &str) -> Option<&'static str> {
+    let language = match extension.to_ascii_lowercase().as_str() {
+        "rs" => "rust",
+        "toml" => "toml",
+        "md" | "markdown" => "markdown",
+        "py" | "pyw" => "python",
+        "sh" => "sh",
+        "bash" => "bash",
+        "zsh" => "zsh",
+        "fish" => "fish",
+        "ps1" => "powershell",
+        "c" | "h" => "c",
+        "cpp" | "cc" | "cxx" | "hpp" | "hh" | "hxx" => "cpp",
+        "cs" => "csharp",
+        "go" => "go",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "swift" => "swift",
+        "js" | "mjs" | "cjs" => "javascript",
+        "ts" | "mts" | "cts" => "typescript",
+        "jsx" => "jsx",
+        "tsx" => "tsx",
+        "rb" => "ruby",
+        "pl" | "pm" => "perl",
+        "lua" => "lua",
+        "hs" => "haskell",
+        "ml" | "mli" => "ocaml",
+        "zig" => "zig",
+        "json" => "json",
+        "yaml" | "yml" => "yaml",
+        "xml" => "xml",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "scss" => "scss",
+        "sql" => "sql",
+        "proto" => "protobuf",
+        "dot" | "gv" => "dot",
+        "pest" => "pest",
+        _ => return None,
+    };
+    Some(language)
+}
+
+fn from_shebang(content: &str) -> Option<&'static str> {
+    let shebang = content.lines().next()?.strip_prefix("#!")?;
+    let mut words = shebang.split_whitespace();
+    let mut interpreter = words.next()?.rsplit('/').next()?;
+    if interpreter == "env" {
+        interpreter = words.find(|word| !word.starts_with('-'))?;
+    }
+    let interpreter = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    let language = match interpreter {
+        "sh" | "dash" => "sh",
+        "bash" => "bash",
+        "zsh" => "zsh",
+        "fish" => "fish",
+        "python" => "python",
+        "node" | "deno" => "javascript",
+        "ruby" => "ruby",
+        "perl" => "perl",
+        "lua" => "lua",
+        "just" => "just",
+        "rust-script" => "rust",
+        _ => return None,
+    };
+    Some(language)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detects_from_extension_and_file_name() {
+        assert_eq!(detect(Path::new("src/main.rs"), ""), Some("rust".into()));
+        assert_eq!(detect(Path::new("linkify.toml"), ""), Some("toml".into()));
+        assert_eq!(detect(Path::new("demo/justfile"), ""), Some("just".into()));
+        assert_eq!(detect(Path::new("notes.unknown"), ""), None);
+    }
+
+    #[test]
+    fn detects_from_shebang() {
+        let script = "#!/usr/bin/env python3\nprint('hi')\n";
+        assert_eq!(detect(Path::new("tool"), script), Some("python".into()));
+        let script = "#!/bin/bash -e\necho hi\n";
+        assert_eq!(detect(Path::new("tool"), script), Some("bash".into()));
+        let script = "#!/usr/bin/env -S rust-script\nfn main() {}\n";
+        assert_eq!(detect(Path::new("tool"), script), Some("rust".into()));
+        assert_eq!(detect(Path::new("tool"), "no shebang"), None);
+    }
+}
@@ -1,15 +1,67 @@
-use std::{collections::BTreeMap, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
 
+use anyhow::Context;
+use ignore::Walk;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 pub use relative_path::RelativePathBuf;
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub mod fence;
+pub mod format;
+pub mod language;
+pub mod parser;
+pub mod paths;
+pub mod usage;
+
+#[derive(
+    Debug, Default, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
 pub struct Snippets {
+    /// Absolute directory which the snippet file paths are relative to.
+    /// Without a base, the paths are absolute, or relative to the working directory in files
+    /// written before format version 3.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<PathBuf>,
+
+    /// Snippets by source file and snippet id.
     pub snippets: BTreeMap<PathBuf, BTreeMap<String, Snippet>>,
 }
 
 impl Snippets {
+    /// Extract the snippets of all files below the absolute `directory`, skipping ignored files.
+    /// File paths are relative to `base`, or absolute without a base.
+    pub fn extract(directory: &Path, base: Option<&Path>) -> anyhow::Result<Self> {
+        let mut map = Snippets {
+            base: base.map(Path::to_path_buf),
+            ..Snippets::default()
+        };
+        for entry in Walk::new(directory).filter_map(Result::ok) {
+            // TODO make this function best-effort with warning output
+            if entry.path().is_file() {
+                let content = read_to_string(entry.path()).with_context(|| {
+                    format!(
+                        "Failed to read source file content: {}",
+                        entry.path().display()
+                    )
+                })?;
+                let path = match base {
+                    Some(base) => paths::relative_to(entry.path(), base),
+                    None => entry.path().to_path_buf(),
+                };
+                let snippets = parser::parse(&content, &path);
+                if !snippets.is_empty() {
+                    map.snippets.insert(path, snippets);
+                }
+            }
+        }
+        Ok(map)
+    }
+
     pub fn snippets_for_id(&self, id: &str) -> Vec<Snippet> {
         let mut results = Vec::new();
         for snippets in self.snippets.values() {
@@ -17,12 +69,75 @@ impl Snippets {
         }
         results
     }
+
+    /// The absolute path of the snippet's source file, joined onto [`Snippets::base`],
+    /// or onto the working directory for relative paths without a base.
+    pub fn absolute_path(&self, snippet: &Snippet) -> PathBuf {
+        let file = Path::new(snippet.file.as_str());
+        let path = match &self.base {
+            _ if file.is_absolute() => file.to_path_buf(),
+            Some(base) => snippet.file.to_path(base),
+            None => snippet.file.to_path(""),
+        };
+        paths::absolute(&path).unwrap_or(path)
+    }
+
+    /// Iterate over all snippets as `(id, snippet)` pairs, ordered by file and id.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Snippet)> {
+        self.snippets
+            .values()
+            .flat_map(|snippets| snippets.iter().map(|(id, snippet)| (id.as_str(), snippet)))
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(
+    Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
 pub struct Snippet {
+    /// Text between the start and end marker lines.
     pub content: String,
+
+    /// Path of the source file, relative to the snippets base directory, or absolute without one.
+    #[schemars(with = "String")]
     pub file: RelativePathBuf,
+
+    /// 0-based line number of the start marker.
     pub line: usize,
+
+    /// Column of the snippet, currently always 0.
     pub col: usize,
+
+    /// Language of the source file, detected from its extension or shebang.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn extracts_absolute_or_relative_paths() {
+        let directory = tempfile::tempdir().unwrap();
+        let directory = paths::absolute(directory.path()).unwrap();
+        std::fs::create_dir(directory.join("src")).unwrap();
+        let file = directory.join("src/main.rs");
+        std::fs::write(
+            &file,
+            "// marker-start:demo\nfn main() {}\n// marker-end:demo\n",
+        )
+        .unwrap();
+
+        let snippets = Snippets::extract(&directory, None).unwrap();
+        assert_eq!(snippets.base, None);
+        let (_, snippet) = snippets.iter().next().unwrap();
+        assert!(Path::new(snippet.file.as_str()).is_absolute());
+        assert_eq!(snippets.snippets.keys().next(), Some(&file));
+        assert_eq!(snippets.absolute_path(snippet), file);
+
+        let snippets = Snippets::extract(&directory, Some(&directory)).unwrap();
+        let (_, snippet) = snippets.iter().next().unwrap();
+        assert_eq!(snippet.file.as_str(), "src/main.rs");
+        assert_eq!(snippets.absolute_path(snippet), file);
+    }
 }
@@ -1,69 +1,102 @@
 use std::{fs::read_to_string, io::Write, path::PathBuf};
 
 use anyhow::Context;
-use clap::Parser as ClapParser;
-use ignore::Walk;
-use parser::parse;
-use path_dedot::ParseDot;
-use snippet_extractor::Snippets;
+use clap::{Parser as ClapParser, Subcommand};
+use regex::Regex;
+use snippet_extractor::{
+    paths::{self, PathBase},
+    Snippets,
+};
 
-pub(crate) mod parser;
+pub(crate) mod query;
 
 #[derive(Debug, ClapParser)]
-#[command(author, version)]
+#[command(
+    author,
+    version,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 pub struct Arguments {
-    #[arg(short, long)]
-    directory: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Directory to extract snippets from. Required unless a subcommand is given.
+    #[arg(short, long, required = true)]
+    directory: Option<PathBuf>,
 
-    #[arg(short, long, default_value_t = false)]
+    /// Store paths relative to the working directory. Shorthand for `--path-base .`.
+    #[arg(short, long, default_value_t = false, conflicts_with = "path_base")]
     relative: bool,
 
+    /// What snippet paths are relative to: `absolute`, `repo`, `markdown` or a directory.
+    #[arg(short, long, default_value = "absolute")]
+    path_base: PathBase,
+
+    /// The markdown document, for `--path-base markdown`.
+    #[arg(short, long)]
+    markdown: Option<PathBuf>,
+
     #[arg(short, long, default_value = "snippets.json")]
     output: Option<PathBuf>,
 }
 
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// List all snippet ids with their location.
+    List {
+        #[arg(short, long, default_value = "snippets.json")]
+        snippets: PathBuf,
+    },
+
+    /// Print the content of a snippet.
+    Show {
+        id: String,
+
+        #[arg(short, long, default_value = "snippets.json")]
+        snippets: PathBuf,
+    },
+
+    /// Search snippet contents for a regex pattern.
+    Grep {
+        pattern: Regex,
+
+        #[arg(short, long, default_value = "snippets.json")]
+        snippets: PathBuf,
+    },
+
+    /// Cross-reference `marker:` fences in markdown files with the extracted snippets.
+    Usage {
+        #[arg(required = true)]
+        markdown: Vec<PathBuf>,
+
+        #[arg(short, long, default_value = "snippets.json")]
+        snippets: PathBuf,
+    },
+
+    /// Print the JSON Schema of the snippets file format.
+    Schema,
+}
+
 fn main() -> anyhow::Result<()> {
     let args = Arguments::parse();
 
-    let mut map = Snippets::default();
-
-    let directory = args
-        .directory
-        .parse_dot()
-        .context("Failed to parse input directory")?;
-    let current_dir = std::env::current_dir().context("Failed to get current directory")?;
-
-    for entry in Walk::new(directory).filter_map(Result::ok) {
-        // TODO make this function best-effort with warning output
-        if entry.path().is_file() {
-            let content = read_to_string(entry.path()).with_context(|| {
-                format!(
-                    "Failed to read source file content: {}",
-                    entry.path().display()
-                )
-            })?;
-            let path = if args.relative {
-                match entry.path().strip_prefix(&current_dir) {
-                    Err(e) => {
-                        eprintln!(
-                            "Warning: Failed to strip path: {} with error {e}",
-                            entry.path().display()
-                        );
-                        entry.path()
-                    }
-                    Ok(stripped) => stripped,
-                }
-            } else {
-                entry.path()
-            };
-            let snippets = parse(&content, path);
-            if !snippets.is_empty() {
-                map.snippets.insert(path.to_path_buf(), snippets);
-            }
-        }
+    if let Some(command) = args.command {
+        return run_query(command);
     }
 
-    let json = serde_json::to_string_pretty(&map)?;
+    let directory = args.directory.context("Missing input directory")?;
+    let directory = paths::absolute(&directory).context("Failed to parse input directory")?;
+
+    let path_base = if args.relative {
+        PathBase::Custom(PathBuf::from("."))
+    } else {
+        args.path_base
+    };
+    let base = path_base.directory(&directory, args.markdown.as_deref())?;
+    let map = Snippets::extract(&directory, base.as_deref())?;
+
+    let json = map.to_json()?;
 
     if let Some(path) = args.output {
         std::fs::write(path, json)?;
@@ -74,3 +107,24 @@ fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+fn load_snippets(path: &PathBuf) -> anyhow::Result<Snippets> {
+    let snippets = read_to_string(path)
+        .with_context(|| format!("Failed to load snippets: {}", path.display()))?;
+    Snippets::from_json(&snippets).context("Failed to parse snippets")
+}
+
+fn run_query(command: Command) -> anyhow::Result<()> {
+    let mut stdout = std::io::stdout();
+    match command {
+        Command::List { snippets } => query::list(&load_snippets(&snippets)?, &mut stdout),
+        Command::Show { id, snippets } => query::show(&load_snippets(&snippets)?, &id, &mut stdout),
+        Command::Grep { pattern, snippets } => {
+            query::grep(&load_snippets(&snippets)?, &pattern, &mut stdout)
+        }
+        Command::Usage { markdown, snippets } => {
+            query::usage(&load_snippets(&snippets)?, &markdown, &mut stdout)
+        }
+        Command::Schema => Ok(writeln!(stdout, "{}", Snippets::json_schema()?)?),
+    }
+}
@@ -1,8 +1,7 @@
 use std::{collections::BTreeMap, path::Path};
 
+use crate::{language, paths, Snippet};
 use itertools::Itertools;
-use relative_path::RelativePathBuf;
-use snippet_extractor::Snippet;
 
 use {once_cell::sync::Lazy, regex::Regex};
 
@@ -12,8 +11,10 @@ static MARKER_START: Lazy<Regex> =
 static MARKER_END: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"[//|#] marker-end:(\w+)").expect("invalid regex"));
 
+/// Extract the snippets between `marker-start:<id>` and `marker-end:<id>` comments of a file.
 pub fn parse(text: &str, file: &Path) -> BTreeMap<String, Snippet> {
     let mut snippets = BTreeMap::default();
+    let language = language::detect(file, text);
     let mut starts: BTreeMap<&str, Vec<(usize, usize)>> = BTreeMap::new();
     let mut ends: BTreeMap<&str, Vec<(usize, usize)>> = BTreeMap::new();
     for (line_number, line) in text.lines().enumerate() {
@@ -59,10 +60,10 @@ pub fn parse(text: &str, file: &Path) -> BTreeMap<String, Snippet> {
                     .join("\n");
                 let snippet = Snippet {
                     content,
-                    file: RelativePathBuf::from_path(file)
-                        .expect("Failed to create portable path buffer"),
+                    file: paths::portable(file),
                     line: start.0,
                     col: 0,
+                    language: language.clone(),
                 };
                 snippets.insert(id.to_string(), snippet);
             }
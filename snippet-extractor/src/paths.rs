@@ -0,0 +1,150 @@
+use std::{
+    convert::Infallible,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::Context;
+use path_dedot::ParseDot;
+use relative_path::RelativePathBuf;
+
+/// What snippet file paths are relative to.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum PathBase {
+    /// Absolute paths.
+    #[default]
+    Absolute,
+    /// Relative to the root of the git repository.
+    Repository,
+    /// Relative to the directory of the markdown document.
+    Markdown,
+    /// Relative to a configured directory.
+    Custom(PathBuf),
+}
+
+/// Parses `absolute`, `repo` and `markdown`. Anything else is a custom base directory.
+impl FromStr for PathBase {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "absolute" => Self::Absolute,
+            "repo" | "repository" => Self::Repository,
+            "markdown" => Self::Markdown,
+            custom => Self::Custom(PathBuf::from(custom)),
+        })
+    }
+}
+
+impl PathBase {
+    /// Resolve the absolute base directory, or `None` for [`PathBase::Absolute`].
+    ///
+    /// The repository root is searched upwards from `start`.
+    pub fn directory(
+        &self,
+        start: &Path,
+        markdown: Option<&Path>,
+    ) -> anyhow::Result<Option<PathBuf>> {
+        let directory = match self {
+            PathBase::Absolute => return Ok(None),
+            PathBase::Repository => repository_root(&absolute(start)?)
+                .with_context(|| format!("No git repository found above {}", start.display()))?,
+            PathBase::Markdown => {
+                let markdown = markdown.context("Markdown path base requires a markdown file")?;
+                let markdown = absolute(markdown)?;
+                markdown
+                    .parent()
+                    .context("Markdown file has no parent directory")?
+                    .to_path_buf()
+            }
+            PathBase::Custom(directory) => absolute(directory)?,
+        };
+        Ok(Some(directory))
+    }
+}
+
+/// Make a path absolute (relative to the working directory) and remove `.` and `..` components.
+pub fn absolute(path: &Path) -> anyhow::Result<PathBuf> {
+    let current_dir = std::env::current_dir().context("Failed to get current directory")?;
+    let path = current_dir.join(path);
+    Ok(path
+        .parse_dot()
+        .with_context(|| format!("Failed to normalize path {}", path.display()))?
+        .into_owned())
+}
+
+/// The closest ancestor of `start` (or `start` itself) which contains a `.git` entry.
+pub fn repository_root(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|directory| directory.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// `path` with `/` separators, as stored in `snippets.json`. Absolute paths stay absolute.
+pub fn portable(path: &Path) -> RelativePathBuf {
+    RelativePathBuf::from_path(path)
+        .unwrap_or_else(|_| RelativePathBuf::from(path.to_string_lossy().replace('\\', "/")))
+}
+
+/// Express `path` relative to `base`, using `..` where necessary.
+pub fn relative_to(path: &Path, base: &Path) -> PathBuf {
+    pathdiff::diff_paths(path, base).unwrap_or_else(|| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_path_bases() {
+        assert_eq!("absolute".parse(), Ok(PathBase::Absolute));
+        assert_eq!("repo".parse(), Ok(PathBase::Repository));
+        assert_eq!("markdown".parse(), Ok(PathBase::Markdown));
+        assert_eq!(
+            "../decks".parse(),
+            Ok(PathBase::Custom(PathBuf::from("../decks")))
+        );
+    }
+
+    #[test]
+    fn resolves_base_directories() {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let root = PathBase::Repository.directory(manifest_dir, None).unwrap();
+        assert_eq!(root.as_deref(), manifest_dir.parent());
+
+        let markdown = manifest_dir.join("README.md");
+        let directory = PathBase::Markdown
+            .directory(manifest_dir, Some(&markdown))
+            .unwrap();
+        assert_eq!(directory.as_deref(), Some(manifest_dir));
+
+        assert_eq!(
+            PathBase::Absolute.directory(manifest_dir, None).unwrap(),
+            None
+        );
+        assert!(PathBase::Markdown.directory(manifest_dir, None).is_err());
+    }
+
+    #[test]
+    fn relativizes_paths() {
+        let path = Path::new("/work/repo/src/main.rs");
+        assert_eq!(
+            relative_to(path, Path::new("/work/repo")),
+            PathBuf::from("src/main.rs")
+        );
+        assert_eq!(
+            relative_to(path, Path::new("/work/repo/slides")),
+            PathBuf::from("../src/main.rs")
+        );
+    }
+
+    #[test]
+    fn keeps_absolute_paths_portable() {
+        assert_eq!(portable(Path::new("src/main.rs")).as_str(), "src/main.rs");
+        assert_eq!(
+            portable(Path::new("/work/repo/src/main.rs")).as_str(),
+            "/work/repo/src/main.rs"
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use std::{fs::read_to_string, io::Write, path::PathBuf};
+
+use anyhow::Context;
+use regex::Regex;
+use snippet_extractor::{
+    usage::{marker_references, Usage},
+    Snippets,
+};
+
+pub fn list(snippets: &Snippets, out: &mut impl Write) -> anyhow::Result<()> {
+    for (id, snippet) in snippets.iter() {
+        writeln!(out, "{id}\t{}:{}", snippet.file, snippet.line + 1)?;
+    }
+    Ok(())
+}
+
+pub fn show(snippets: &Snippets, id: &str, out: &mut impl Write) -> anyhow::Result<()> {
+    let found = snippets.snippets_for_id(id);
+    if found.is_empty() {
+        anyhow::bail!("No snippet with id '{id}'");
+    }
+    let many = found.len() > 1;
+    for snippet in found {
+        if many {
+            writeln!(out, "==> {}:{} <==", snippet.file, snippet.line + 1)?;
+        }
+        writeln!(out, "{}", snippet.content.trim_end_matches('\n'))?;
+    }
+    Ok(())
+}
+
+pub fn grep(snippets: &Snippets, pattern: &Regex, out: &mut impl Write) -> anyhow::Result<()> {
+    for (id, snippet) in snippets.iter() {
+        for (number, line) in snippet.content.lines().enumerate() {
+            if pattern.is_match(line) {
+                // The content starts on the line after the start marker.
+                let line_number = snippet.line + number + 2;
+                writeln!(out, "{id}\t{}:{line_number}: {line}", snippet.file)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn usage(snippets: &Snippets, files: &[PathBuf], out: &mut impl Write) -> anyhow::Result<()> {
+    let mut references = Vec::new();
+    for file in files {
+        let markdown = read_to_string(file)
+            .with_context(|| format!("Failed to read markdown file: {}", file.display()))?;
+        references.extend(marker_references(&markdown, file));
+    }
+    let usage = Usage::new(snippets, references);
+
+    writeln!(out, "Used snippets:")?;
+    for (id, references) in &usage.used {
+        writeln!(out, "  {id}")?;
+        for reference in references {
+            writeln!(out, "    {}:{}", reference.file.display(), reference.line)?;
+        }
+    }
+    writeln!(out, "Unused snippets:")?;
+    for id in &usage.unused {
+        writeln!(out, "  {id}")?;
+    }
+    writeln!(out, "Unresolved markers:")?;
+    for reference in &usage.unresolved {
+        writeln!(
+            out,
+            "  {}:{}: {}",
+            reference.file.display(),
+            reference.line,
+            reference.marker
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use snippet_extractor::{RelativePathBuf, Snippet};
+
+    use super::*;
+
+    fn snippets(entries: &[(&str, &str, usize, &str)]) -> Snippets {
+        let mut snippets = Snippets::default();
+        for &(file, id, line, content) in entries {
+            let snippet = Snippet {
+                content: content.to_string(),
+                file: RelativePathBuf::from(file),
+                line,
+                col: 0,
+                language: None,
+            };
+            snippets
+                .snippets
+                .entry(PathBuf::from(file))
+                .or_default()
+                .insert(id.to_string(), snippet);
+        }
+        snippets
+    }
+
+    fn output(run: impl FnOnce(&mut Vec<u8>) -> anyhow::Result<()>) -> anyhow::Result<String> {
+        let mut out = Vec::new();
+        run(&mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn lists_and_shows_snippets() {
+        let snippets = snippets(&[
+            ("b.rs", "shared", 4, "fn b() {}\n"),
+            ("a.rs", "shared", 0, "fn a() {}\n"),
+            ("a.rs", "only", 9, "let x = 1;\n"),
+        ]);
+        assert_eq!(
+            output(|out| list(&snippets, out)).unwrap(),
+            "only\ta.rs:10\nshared\ta.rs:1\nshared\tb.rs:5\n"
+        );
+        assert_eq!(
+            output(|out| show(&snippets, "only", out)).unwrap(),
+            "let x = 1;\n"
+        );
+        assert_eq!(
+            output(|out| show(&snippets, "shared", out)).unwrap(),
+            "==> a.rs:1 <==\nfn a() {}\n==> b.rs:5 <==\nfn b() {}\n"
+        );
+        assert_eq!(
+            output(|out| show(&snippets, "missing", out))
+                .unwrap_err()
+                .to_string(),
+            "No snippet with id 'missing'"
+        );
+    }
+
+    #[test]
+    fn greps_snippet_lines() {
+        let snippets = snippets(&[
+            ("a.rs", "first", 2, "let a = 1;\nlet b = a + 1;\n"),
+            ("b.rs", "second", 0, "fn main() {}\n"),
+        ]);
+        let pattern = Regex::new(r"\ba\b").unwrap();
+        assert_eq!(
+            output(|out| grep(&snippets, &pattern, out)).unwrap(),
+            "first\ta.rs:4: let a = 1;\nfirst\ta.rs:5: let b = a + 1;\n"
+        );
+        let pattern = Regex::new("unmatched").unwrap();
+        assert_eq!(output(|out| grep(&snippets, &pattern, out)).unwrap(), "");
+    }
+}
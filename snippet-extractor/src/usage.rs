@@ -0,0 +1,128 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use pulldown_cmark::{CodeBlockKind, Event, Parser, Tag};
+
+use crate::{fence::FenceInfo, Snippets};
+
+/// A `marker:` reference in the fence of a markdown code block.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MarkerReference {
+    pub marker: String,
+    pub file: PathBuf,
+    pub line: usize,
+}
+
+/// Find all `marker:` references in a markdown document.
+/// Line numbers are 1-based and point at the opening fence.
+pub fn marker_references(markdown: &str, file: &Path) -> Vec<MarkerReference> {
+    Parser::new(markdown)
+        .into_offset_iter()
+        .filter_map(|(event, range)| {
+            let Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(fence))) = event else {
+                return None;
+            };
+            let (fence, _errors) = FenceInfo::parse_lossy(&fence);
+            let marker = fence.get("marker")?.to_string();
+            let line = markdown[..range.start].matches('\n').count() + 1;
+            Some(MarkerReference {
+                marker,
+                file: file.to_path_buf(),
+                line,
+            })
+        })
+        .collect()
+}
+
+/// Cross-reference of snippets and the markdown fences referring to them.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Usage {
+    /// References per snippet id, for all snippets which are used at least once.
+    pub used: BTreeMap<String, Vec<MarkerReference>>,
+    /// Snippet ids which are never referenced.
+    pub unused: Vec<String>,
+    /// References to snippet ids which do not exist.
+    pub unresolved: Vec<MarkerReference>,
+}
+
+impl Usage {
+    pub fn new(snippets: &Snippets, references: impl IntoIterator<Item = MarkerReference>) -> Self {
+        let mut usage = Self::default();
+        for reference in references {
+            if snippets.snippets_for_id(&reference.marker).is_empty() {
+                usage.unresolved.push(reference);
+            } else {
+                usage
+                    .used
+                    .entry(reference.marker.clone())
+                    .or_default()
+                    .push(reference);
+            }
+        }
+        let mut unused = snippets
+            .iter()
+            .map(|(id, _)| id)
+            .filter(|id| !usage.used.contains_key(*id))
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        unused.sort();
+        unused.dedup();
+        usage.unused = unused;
+        usage
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use relative_path::RelativePathBuf;
+
+    use super::*;
+    use crate::Snippet;
+
+    fn snippets(ids: &[&str]) -> Snippets {
+        let mut snippets = Snippets::default();
+        let file = snippets
+            .snippets
+            .entry(PathBuf::from("main.rs"))
+            .or_default();
+        for (line, id) in ids.iter().enumerate() {
+            let snippet = Snippet {
+                content: String::new(),
+                file: RelativePathBuf::from("main.rs"),
+                line,
+                col: 0,
+                language: None,
+            };
+            file.insert(id.to_string(), snippet);
+        }
+        snippets
+    }
+
+    #[test]
+    fn finds_marker_references_with_lines() {
+        let md =
+            "# Title\n\n```rust marker:first\n```\n\ntext\n\n````bash tag:x marker:second\n````\n";
+        let references = marker_references(md, Path::new("deck.md"));
+        let found = references
+            .iter()
+            .map(|r| (r.marker.as_str(), r.line))
+            .collect::<Vec<_>>();
+        assert_eq!(found, vec![("first", 3), ("second", 8)]);
+    }
+
+    #[test]
+    fn cross_references_usage() {
+        let md =
+            "```rust marker:used\n```\n\n```rust marker:missing\n```\n\n```rust marker:used\n```\n";
+        let references = marker_references(md, Path::new("deck.md"));
+        let usage = Usage::new(&snippets(&["used", "unused"]), references);
+
+        assert_eq!(usage.used["used"].len(), 2);
+        assert_eq!(usage.unused, vec!["unused".to_string()]);
+        assert_eq!(usage.unresolved.len(), 1);
+        assert_eq!(usage.unresolved[0].marker, "missing");
+        assert_eq!(usage.unresolved[0].line, 4);
+    }
+}